@@ -3,6 +3,7 @@ use std::{collections::HashMap, error::Error as StdError, fmt::Display};
 use josekit::{jwe::JweEncrypter, jws::JwsSigner};
 use serde::Deserialize;
 use verder_helpen_jwt::{EncryptionKeyConfig, SignKeyConfig};
+use verder_helpen_proto::CoreAuth;
 
 #[derive(Debug)]
 pub enum Error {
@@ -61,6 +62,9 @@ struct RawConfig {
     with_session: bool,
     encryption_pubkey: EncryptionKeyConfig,
     signing_privkey: SignKeyConfig,
+    /// How calls from core are authenticated, unchecked when absent
+    #[serde(default)]
+    core_auth: Option<CoreAuth>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -72,6 +76,7 @@ pub struct Config {
     with_session: bool,
     encrypter: Box<dyn JweEncrypter>,
     signer: Box<dyn JwsSigner>,
+    core_auth: Option<CoreAuth>,
 }
 
 // This tryfrom can be removed once try_from for fields lands in serde
@@ -86,6 +91,7 @@ impl TryFrom<RawConfig> for Config {
             with_session: config.with_session,
             encrypter: Box::<dyn JweEncrypter>::try_from(config.encryption_pubkey)?,
             signer: Box::<dyn JwsSigner>::try_from(config.signing_privkey)?,
+            core_auth: config.core_auth,
         })
     }
 }
@@ -135,4 +141,8 @@ impl Config {
     pub fn signer(&self) -> &dyn JwsSigner {
         self.signer.as_ref()
     }
+
+    pub fn core_auth(&self) -> Option<&CoreAuth> {
+        self.core_auth.as_ref()
+    }
 }
@@ -9,11 +9,12 @@ use rocket::{
     response::{content::RawHtml, Redirect},
     routes,
     serde::json::Json,
-    State,
+    Either, State,
 };
 use verder_helpen_jwt::sign_and_encrypt_auth_result;
 use verder_helpen_proto::{
-    AuthResult, AuthStatus, SessionActivity, StartAuthRequest, StartAuthResponse,
+    AuthResult, AuthStatus, Capability, FromCore, SessionActivity, StartAuthRequest,
+    StartAuthResponse,
 };
 
 mod config;
@@ -105,6 +106,13 @@ struct ConfirmTemplate {
     attributes: HashMap<String, String>,
 }
 
+#[derive(Template)]
+#[template(path = "form_post.html")]
+struct FormPostTemplate<'a> {
+    continuation: &'a str,
+    result: &'a str,
+}
+
 #[derive(FromForm, Debug)]
 struct SessionUpdateData {
     r#type: SessionActivity,
@@ -140,23 +148,30 @@ fn confirm_oob(
     Ok(RawHtml(output))
 }
 
-#[get("/confirm/<attributes>/<continuation>")]
+#[get("/confirm/<attributes>/<continuation>?<form_post>")]
 fn confirm_inline(
     config: &State<config::Config>,
     attributes: &str,
     continuation: &str,
+    form_post: bool,
 ) -> Result<RawHtml<String>, Error> {
     let values = config.map_attributes(&serde_json::from_slice::<Vec<String>>(
         &base64::decode_config(attributes, URL_SAFE_NO_PAD)?,
     )?)?;
     let template = ConfirmTemplate {
         dologin: format!(
-            "{}/browser/{}/{}",
+            "{}/browser/{}/{}?form_post={}",
             config.server_url(),
             attributes,
-            continuation
+            continuation,
+            form_post
+        ),
+        dologout: format!(
+            "{}/cancel/{}?form_post={}",
+            config.server_url(),
+            continuation,
+            form_post
         ),
-        dologout: format!("{}/cancel/{}", config.server_url(), continuation),
         attributes: values,
     };
     let output = template.render()?;
@@ -254,29 +269,44 @@ fn redirect_user(
     auth_result: AuthResult,
     config: &State<config::Config>,
     continuation: &str,
-) -> Result<Redirect, Error> {
+    form_post: bool,
+) -> Result<Either<Redirect, RawHtml<String>>, Error> {
     let auth_result =
         sign_and_encrypt_auth_result(&auth_result, config.signer(), config.encrypter())?;
     let continuation = base64::decode_config(continuation, URL_SAFE_NO_PAD)?;
     let continuation = std::str::from_utf8(&continuation)?;
 
+    if form_post {
+        println!("Posting auth result {} to {continuation}", &auth_result);
+        let template = FormPostTemplate {
+            continuation,
+            result: &auth_result,
+        };
+        return Ok(Either::Right(RawHtml(template.render()?)));
+    }
+
     println!(
         "Redirecting user to {continuation} with auth result {}",
         &auth_result
     );
     if continuation.contains('?') {
-        Ok(Redirect::to(format!("{continuation}&result={auth_result}")))
+        Ok(Either::Left(Redirect::to(format!(
+            "{continuation}&result={auth_result}"
+        ))))
     } else {
-        Ok(Redirect::to(format!("{continuation}?result={auth_result}")))
+        Ok(Either::Left(Redirect::to(format!(
+            "{continuation}?result={auth_result}"
+        ))))
     }
 }
 
-#[post("/browser/<attributes>/<continuation>")]
+#[post("/browser/<attributes>/<continuation>?<form_post>")]
 fn user_inline(
     config: &State<config::Config>,
     attributes: &str,
     continuation: &str,
-) -> Result<Redirect, Error> {
+    form_post: bool,
+) -> Result<Either<Redirect, RawHtml<String>>, Error> {
     let attributes = base64::decode_config(attributes, URL_SAFE_NO_PAD)?;
     let attributes: Vec<String> = serde_json::from_slice(&attributes)?;
     let attributes = config.map_attributes(&attributes)?;
@@ -286,24 +316,28 @@ fn user_inline(
         session_url: session_url(config),
     };
 
-    redirect_user(auth_result, config, continuation)
+    redirect_user(auth_result, config, continuation, form_post)
 }
 
-#[post("/cancel/<continuation>")]
-fn cancel_inline(config: &State<config::Config>, continuation: &str) -> Result<Redirect, Error> {
+#[post("/cancel/<continuation>?<form_post>")]
+fn cancel_inline(
+    config: &State<config::Config>,
+    continuation: &str,
+    form_post: bool,
+) -> Result<Either<Redirect, RawHtml<String>>, Error> {
     let auth_result = AuthResult {
         status: AuthStatus::Failed,
         attributes: Some(HashMap::new()),
         session_url: session_url(config),
     };
 
-    redirect_user(auth_result, config, continuation)
+    redirect_user(auth_result, config, continuation, form_post)
 }
 
 #[post("/start_authentication", data = "<request>")]
 fn start_authentication(
     config: &State<config::Config>,
-    request: Json<StartAuthRequest>,
+    request: FromCore<StartAuthRequest>,
 ) -> Result<Json<StartAuthResponse>, Error> {
     config.verify_attributes(&request.attributes)?;
 
@@ -324,12 +358,16 @@ fn start_authentication(
             ),
         }))
     } else {
+        // Results go along with the user, posted when the continuation
+        // supports it
+        let form_post = request.capabilities.contains(&Capability::FormPost);
         Ok(Json(StartAuthResponse {
             client_url: format!(
-                "{}/confirm/{}/{}",
+                "{}/confirm/{}/{}?form_post={}",
                 config.server_url(),
                 attributes,
                 continuation,
+                form_post,
             ),
         }))
     }
@@ -356,5 +394,9 @@ fn rocket() -> _ {
         .extract::<Config>()
         .unwrap_or_else(|e| panic!("Failure to parse configuration: {e:?}"));
 
+    let base = match config.core_auth() {
+        Some(core_auth) => base.manage(core_auth.clone()),
+        None => base,
+    };
     base.manage(config)
 }
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use josekit::{jwe::JweDecrypter, jws::JwsVerifier};
 use serde::Deserialize;
 use verder_helpen_jwt::{EncryptionKeyConfig, SignKeyConfig};
+use verder_helpen_proto::CoreAuth;
 
 #[cfg(feature = "auth_during_comm")]
 pub(crate) use self::auth_during_comm::{AuthDuringCommConfig, RawAuthDuringCommConfig};
@@ -36,6 +37,15 @@ pub struct RawConfig {
     auth_during_comm: RawAuthDuringCommConfig,
 
     custom_css: Option<String>,
+
+    /// Plugin-specific attribute names mapped to their canonical names, per
+    /// auth method
+    #[serde(default)]
+    attribute_aliases: HashMap<String, HashMap<String, String>>,
+
+    /// How calls from core are authenticated, unchecked when absent
+    #[serde(default)]
+    core_auth: Option<CoreAuth>,
 }
 
 /// configuration container for a typical verder-helpen communication plugin
@@ -58,6 +68,10 @@ pub struct Config {
     pub auth_during_comm: AuthDuringCommConfig,
 
     pub custom_css: Option<String>,
+
+    pub attribute_aliases: HashMap<String, HashMap<String, String>>,
+
+    pub core_auth: Option<CoreAuth>,
 }
 
 // This tryfrom can be removed once try_from for fields lands in serde
@@ -85,6 +99,8 @@ impl TryFrom<RawConfig> for Config {
             decrypter: Box::<dyn JweDecrypter>::try_from(raw_config.decryption_privkey)?,
             verifier: Box::<dyn JwsVerifier>::try_from(raw_config.signature_pubkey)?,
             custom_css: raw_config.custom_css,
+            attribute_aliases: raw_config.attribute_aliases,
+            core_auth: raw_config.core_auth,
         })
     }
 }
@@ -120,6 +136,17 @@ impl Config {
         &self.translations
     }
 
+    /// Aliases for the attribute names used by the given auth method
+    pub fn attribute_aliases(&self, auth_method: &str) -> Option<&HashMap<String, String>> {
+        self.attribute_aliases.get(auth_method)
+    }
+
+    /// Authentication of calls from core, to be managed by the plugin so
+    /// [`FromCore`](verder_helpen_proto::FromCore) request bodies are checked
+    pub fn core_auth(&self) -> Option<&CoreAuth> {
+        self.core_auth.as_ref()
+    }
+
     pub fn auth_provider(&self) -> &Option<auth::AuthProvider> {
         &self.auth_provider
     }
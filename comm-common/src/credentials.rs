@@ -22,12 +22,20 @@ pub fn collect_credentials(
 
     for guest_auth_result in guest_auth_results {
         let attributes = if let Some(result) = &guest_auth_result.auth_result {
-            verder_helpen_jwt::dangerous_decrypt_auth_result_without_verifying_expiration(
-                result,
-                config.verifier(),
-                config.decrypter(),
-            )?
-            .attributes
+            let result =
+                verder_helpen_jwt::dangerous_decrypt_auth_result_without_verifying_expiration(
+                    result,
+                    config.verifier(),
+                    config.decrypter(),
+                )?;
+            let aliases = guest_auth_result
+                .auth_method
+                .as_deref()
+                .and_then(|auth_method| config.attribute_aliases(auth_method));
+            match aliases {
+                Some(aliases) => result.canonicalize_attributes(aliases).attributes,
+                None => result.attributes,
+            }
         } else {
             None
         };
@@ -129,6 +137,7 @@ pub async fn get_credentials_for_host(
             purpose: Some(session.guest_token.purpose),
             name: Some(session.guest_token.name),
             auth_result: session.auth_result,
+            auth_method: session.auth_method,
             created_at: session.created_at,
         })
         .collect::<Vec<GuestAuthResult>>();
@@ -176,6 +185,126 @@ mod tests {
         s.chars().filter(|c| !c.is_whitespace()).collect()
     }
 
+    fn test_config(attribute_aliases: HashMap<String, HashMap<String, String>>) -> Config {
+        let dec_config: EncryptionKeyConfig = serde_yaml::from_str(EC_PRIVKEY).unwrap();
+        let decrypter = Box::<dyn JweDecrypter>::try_from(dec_config).unwrap();
+
+        let ver_config: SignKeyConfig = serde_yaml::from_str(EC_PUBKEY).unwrap();
+        let verifier = Box::<dyn JwsVerifier>::try_from(ver_config).unwrap();
+
+        let widget_sig_config: SignKeyConfig = serde_yaml::from_str(EC_PRIVKEY).unwrap();
+        let widget_signer = Box::<dyn JwsSigner>::try_from(widget_sig_config).unwrap();
+        let start_auth_signer = widget_signer.clone();
+        let guest_verifier = HmacJwsAlgorithm::Hs256
+            .verifier_from_bytes(GUEST_SECRET)
+            .unwrap();
+        let host_verifier = HmacJwsAlgorithm::Hs256
+            .verifier_from_bytes(HOST_SECRET)
+            .unwrap();
+
+        let auth_during_comm = AuthDuringCommConfig {
+            core_url: "https://example.com".to_string(),
+            widget_url: "https://example.com".to_string(),
+            display_name: "comm-common".to_string(),
+            widget_signer,
+            start_auth_signer,
+            start_auth_key_id: "not-needed".into(),
+            guest_verifier: Box::new(guest_verifier),
+            host_verifier: Box::new(host_verifier),
+        };
+
+        Config {
+            internal_url: "https://example.com".to_string(),
+            external_host_url: None,
+            external_guest_url: None,
+            default_locale: String::from("nl"),
+            translations: HashMap::new(),
+            decrypter,
+            auth_provider: None,
+            verifier,
+            auth_during_comm,
+            custom_css: None,
+            attribute_aliases,
+            core_auth: None,
+        }
+    }
+
+    fn guest_auth_result(
+        attributes: &[(&str, &str)],
+        auth_method: Option<&str>,
+    ) -> GuestAuthResult {
+        let enc_config: EncryptionKeyConfig = serde_yaml::from_str(EC_PUBKEY).unwrap();
+        let encrypter = Box::<dyn JweEncrypter>::try_from(enc_config).unwrap();
+        let sig_config: SignKeyConfig = serde_yaml::from_str(EC_PRIVKEY).unwrap();
+        let signer = Box::<dyn JwsSigner>::try_from(sig_config).unwrap();
+
+        let in_result = AuthResult {
+            status: AuthStatus::Success,
+            attributes: Some(
+                attributes
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            ),
+            session_url: None,
+        };
+        let jwe =
+            sign_and_encrypt_auth_result(&in_result, signer.as_ref(), encrypter.as_ref()).unwrap();
+
+        GuestAuthResult {
+            purpose: Some("test_purpose".to_string()),
+            name: Some("John Doe".to_string()),
+            auth_result: Some(jwe),
+            auth_method: auth_method.map(str::to_string),
+            created_at: DateTime::from_timestamp_nanos(0),
+        }
+    }
+
+    #[test]
+    fn test_attribute_aliases_per_auth_method() {
+        let config = test_config(HashMap::from([
+            (
+                "irma".to_string(),
+                HashMap::from([(
+                    "pbdf.sidn-pbdf.email.email".to_string(),
+                    "email".to_string(),
+                )]),
+            ),
+            (
+                "other".to_string(),
+                HashMap::from([("mail".to_string(), "email".to_string())]),
+            ),
+        ]));
+
+        // The same name is only mapped for the auth method that uses it
+        let credentials = collect_credentials(
+            &[
+                guest_auth_result(
+                    &[("pbdf.sidn-pbdf.email.email", "a@example.com")],
+                    Some("irma"),
+                ),
+                guest_auth_result(&[("mail", "b@example.com")], Some("other")),
+                guest_auth_result(&[("mail", "c@example.com")], Some("irma")),
+                guest_auth_result(&[("mail", "d@example.com")], None),
+            ],
+            &config,
+        )
+        .unwrap();
+        let attributes: Vec<_> = credentials
+            .into_iter()
+            .map(|c| c.attributes.unwrap().into_iter().collect::<Vec<_>>())
+            .collect();
+        assert_eq!(
+            attributes,
+            vec![
+                vec![("email".to_string(), "a@example.com".to_string())],
+                vec![("email".to_string(), "b@example.com".to_string())],
+                vec![("mail".to_string(), "c@example.com".to_string())],
+                vec![("mail".to_string(), "d@example.com".to_string())],
+            ]
+        );
+    }
+
     #[test]
     fn render_test() {
         let enc_config: EncryptionKeyConfig = serde_yaml::from_str(EC_PUBKEY).unwrap();
@@ -217,6 +346,7 @@ mod tests {
             purpose: Some("test_purpose".to_string()),
             name: Some("John Doe".to_string()),
             auth_result: Some(jwe),
+            auth_method: None,
             created_at: DateTime::from_timestamp_nanos(0),
         }];
 
@@ -242,6 +372,8 @@ mod tests {
             verifier,
             auth_during_comm,
             custom_css: None,
+            attribute_aliases: HashMap::new(),
+            core_auth: None,
         };
 
         let translations = Translations {
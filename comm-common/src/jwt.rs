@@ -7,6 +7,8 @@ use thiserror::Error;
 use verder_helpen_proto::StartRequestAuthOnly;
 
 use crate::types::AuthSelectParams;
+#[cfg(feature = "auth_during_comm")]
+use crate::util::random_string;
 
 #[derive(Error, Debug)]
 pub enum JwtError {
@@ -31,6 +33,8 @@ pub fn sign_start_auth_request(
     sig_header.set_key_id(kid);
     let mut sig_payload = JwtPayload::new();
     sig_payload.set_claim("request", Some(serde_json::to_value(request)?))?;
+    // Unique ID allowing core to reject replayed requests
+    sig_payload.set_jwt_id(random_string(32));
     sig_payload.set_issued_at(&std::time::SystemTime::now());
     sig_payload
         .set_expires_at(&(std::time::SystemTime::now() + std::time::Duration::from_secs(5 * 60)));
@@ -147,6 +151,7 @@ mod tests {
         let (payload, header) =
             josekit::jwt::decode_with_verifier(result, verifier.as_ref()).unwrap();
         assert_eq!(header.key_id(), Some("some"));
+        assert_eq!(payload.jwt_id().map(str::len), Some(32));
         let mut validator = JwtPayloadValidator::new();
         validator.set_base_time(std::time::SystemTime::now());
         validator.validate(&payload).unwrap();
@@ -41,4 +41,5 @@ pub mod prelude {
         types::{AuthSelectParams, Credentials, GuestAuthResult, StartRequest},
         util::random_string,
     };
+    pub use verder_helpen_proto::{CoreAuth, FromCore};
 }
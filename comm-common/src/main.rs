@@ -7,6 +7,7 @@ use auth::Authorized;
 use config::Config;
 use error::Error;
 use rocket::{
+    form::Form,
     get, post,
     response::{
         content::{RawCss, RawJavaScript},
@@ -25,7 +26,9 @@ use session::{Session, SessionDBConn};
 use templates::{RenderType, RenderedContent};
 use translations::Translations;
 use types::{AuthSelectParams, FromPlatformJwt, GuestToken, HostToken, StartRequest};
-use verder_helpen_proto::{ClientUrlResponse, StartRequestAuthOnly};
+use verder_helpen_proto::{
+    ClientUrlResponse, Correlation, CorrelationFairing, StartRequestAuthOnly,
+};
 
 mod auth;
 mod config;
@@ -72,20 +75,62 @@ fn init(guest_token: &str, config: &State<Config>) -> Result<Redirect, Error> {
     Ok(Redirect::to(uri))
 }
 
-#[post("/start/<guest_token>", data = "<start_request>")]
+#[post("/start/<guest_token>", data = "<start_request>", rank = 2)]
 async fn start(
     guest_token: &str,
     start_request: &str,
     config: &State<Config>,
     db: SessionDBConn,
     queue: &State<Sender<AttributesUpdateEvent>>,
+    correlation: &Correlation,
 ) -> Result<Json<ClientUrlResponse>, Error> {
+    let start_request = serde_json::from_str(start_request)?;
+    let client_url_response =
+        start_guest_session(guest_token, start_request, config, &db, queue, correlation).await?;
+    Ok(Json(client_url_response))
+}
+
+/// Start from the selection page in core, which posts the chosen auth method
+/// as a form instead of calling this endpoint from a script
+#[post(
+    "/start/<guest_token>",
+    format = "application/x-www-form-urlencoded",
+    data = "<start_request>"
+)]
+async fn start_form(
+    guest_token: &str,
+    start_request: Form<StartRequest>,
+    config: &State<Config>,
+    db: SessionDBConn,
+    queue: &State<Sender<AttributesUpdateEvent>>,
+    correlation: &Correlation,
+) -> Result<Redirect, Error> {
+    let client_url_response = start_guest_session(
+        guest_token,
+        start_request.into_inner(),
+        config,
+        &db,
+        queue,
+        correlation,
+    )
+    .await?;
+    Ok(Redirect::to(client_url_response.client_url))
+}
+
+async fn start_guest_session(
+    guest_token: &str,
+    start_request: StartRequest,
+    config: &Config,
+    db: &SessionDBConn,
+    queue: &Sender<AttributesUpdateEvent>,
+    correlation: &Correlation,
+) -> Result<ClientUrlResponse, Error> {
     let guest_token =
         GuestToken::from_platform_jwt(guest_token, config.auth_during_comm().guest_verifier())?;
     let StartRequest {
         purpose,
         auth_method,
-    } = serde_json::from_str(start_request)?;
+    } = start_request;
 
     if purpose != guest_token.purpose {
         return Err(Error::BadRequest(
@@ -97,10 +142,17 @@ async fn start(
     let comm_url = guest_token.redirect_url.clone();
     let attr_url = format!("{}/auth_result/{}", config.internal_url(), attr_id);
     let purpose = guest_token.purpose.clone();
-    if !Session::restart_auth(guest_token.clone(), attr_id.clone(), &db).await? {
-        let session = Session::new(guest_token, attr_id.clone());
-
-        session.persist(&db).await?;
+    if !Session::restart_auth(
+        guest_token.clone(),
+        attr_id.clone(),
+        auth_method.clone(),
+        db,
+    )
+    .await?
+    {
+        let session = Session::new(guest_token, attr_id.clone(), auth_method.clone());
+
+        session.persist(db).await?;
     }
 
     let start_request = StartRequestAuthOnly {
@@ -116,9 +168,12 @@ async fn start(
         config.auth_during_comm().start_auth_signer(),
     )?;
 
-    let client = reqwest::Client::new();
-    let client_url_response = client
-        .post(format!("{}/start", config.auth_during_comm().core_url()))
+    let mut request =
+        reqwest::Client::new().post(format!("{}/start", config.auth_during_comm().core_url()));
+    for (name, value) in correlation.headers() {
+        request = request.header(name, value);
+    }
+    let client_url_response = request
         .header(
             reqwest::header::ACCEPT,
             reqwest::header::HeaderValue::from_static("application/json"),
@@ -140,8 +195,9 @@ async fn start(
         Err(_) => println!("Err, no update sent"),
     }
 
-    let client_url_response = serde_json::from_str::<ClientUrlResponse>(&client_url_response)?;
-    Ok(Json(client_url_response))
+    Ok(serde_json::from_str::<ClientUrlResponse>(
+        &client_url_response,
+    )?)
 }
 
 #[post("/auth_result/<attr_id>", data = "<auth_result>")]
@@ -151,7 +207,12 @@ async fn auth_result(
     config: &State<Config>,
     db: SessionDBConn,
     queue: &State<Sender<AttributesUpdateEvent>>,
+    correlation: &Correlation,
 ) -> Result<(), Error> {
+    println!(
+        "Received authentication result for {attr_id} (request {})",
+        correlation.request_id()
+    );
     verder_helpen_jwt::decrypt_and_verify_auth_result(
         auth_result,
         config.verifier(),
@@ -285,12 +346,13 @@ async fn main() -> Result<(), rocket::Error> {
     let mut base = rocket::build()
         .manage(channel::<AttributesUpdateEvent>(1024).0)
         .mount("/internal", routes![auth_result, clean_db,])
-        .mount("/guest", routes![init, start,])
+        .mount("/guest", routes![init, start, start_form,])
         .mount(
             "/host",
             routes![session_info, attribute_ui, attribute_css, attribute_js,],
         )
-        .attach(SessionDBConn::fairing());
+        .attach(SessionDBConn::fairing())
+        .attach(CorrelationFairing);
 
     let config = base.figment().extract::<Config>().unwrap_or_else(|_| {
         // Drop error value, as it could contain secrets
@@ -302,6 +364,10 @@ async fn main() -> Result<(), rocket::Error> {
         base = base.attach(auth_provider.fairing());
     }
 
+    if let Some(core_auth) = config.core_auth() {
+        base = base.manage(core_auth.clone());
+    }
+
     let base = base
         .manage(config)
         .ignite()
@@ -16,6 +16,8 @@ pub struct Session {
     pub guest_token: GuestToken,
     /// The autheniction result. `None` if none was received yet
     pub auth_result: Option<String>,
+    /// The auth method chosen by the guest
+    pub auth_method: Option<String>,
     /// ID used to match incoming attributes with this session
     pub attr_id: String,
     /// The date and time the session was created
@@ -24,11 +26,12 @@ pub struct Session {
 
 impl Session {
     /// Create a new session
-    pub fn new(guest_token: GuestToken, attr_id: String) -> Self {
+    pub fn new(guest_token: GuestToken, attr_id: String, auth_method: String) -> Self {
         Self {
             attr_id,
             guest_token,
             auth_result: None,
+            auth_method: Some(auth_method),
             created_at: Utc::now(),
         }
     }
@@ -48,9 +51,10 @@ impl Session {
                         name,
                         attr_id,
                         auth_result,
+                        auth_method,
                         created_at,
                         last_activity
-                    ) VALUES ($1, $2, $3, $4, $5, $6, $7, now(), now());",
+                    ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, now(), now());",
                     &[
                         &this.guest_token.id,
                         &this.guest_token.room_id,
@@ -59,6 +63,7 @@ impl Session {
                         &this.guest_token.name,
                         &this.attr_id,
                         &this.auth_result,
+                        &this.auth_method,
                     ],
                 )
             })
@@ -98,12 +103,13 @@ impl Session {
     pub async fn restart_auth(
         token: GuestToken,
         new_attr_id: String,
+        auth_method: String,
         db: &SessionDBConn,
     ) -> Result<bool, Error> {
         let n = db
             .run(move |c| {
                 c.execute(
-                    "UPDATE session SET attr_id=$1 WHERE
+                    "UPDATE session SET (attr_id, auth_method) = ($1, $7) WHERE
                         session_id = $2 AND
                         room_id = $3 AND
                         redirect_url = $4 AND
@@ -117,6 +123,7 @@ impl Session {
                         &token.redirect_url,
                         &token.purpose,
                         &token.name,
+                        &auth_method,
                     ],
                 )
             })
@@ -167,6 +174,7 @@ impl Session {
                         name,
                         attr_id,
                         auth_result,
+                        auth_method,
                         created_at
                     ",
                     &[&room_id],
@@ -187,6 +195,7 @@ impl Session {
                             guest_token,
                             attr_id: r.get("attr_id"),
                             auth_result: r.get("auth_result"),
+                            auth_method: r.get("auth_method"),
                             created_at: r.get("created_at"),
                         })
                     })
@@ -284,6 +293,7 @@ session = {{ url = "{}" }}
         Session {
             guest_token,
             auth_result: None,
+            auth_method: Some("test".to_owned()),
             attr_id: random_string(32),
             created_at: Utc::now(),
         }
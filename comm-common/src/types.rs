@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, rocket::FromForm)]
 pub struct StartRequest {
     pub purpose: String,
     pub auth_method: String,
@@ -28,6 +28,8 @@ pub struct GuestAuthResult {
     pub purpose: Option<String>,
     pub name: Option<String>,
     pub auth_result: Option<String>,
+    /// Auth method that produced the result, unknown for older sessions
+    pub auth_method: Option<String>,
     pub created_at: DateTime<Utc>,
 }
 
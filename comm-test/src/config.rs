@@ -3,6 +3,7 @@ use std::{error::Error as StdError, fmt::Display};
 use josekit::{jwe::JweDecrypter, jws::JwsVerifier};
 use serde::Deserialize;
 use verder_helpen_jwt::{EncryptionKeyConfig, SignKeyConfig};
+use verder_helpen_proto::CoreAuth;
 
 #[derive(Debug)]
 pub enum Error {
@@ -61,6 +62,9 @@ struct RawConfig {
     use_attr_url: bool,
     decryption_privkey: EncryptionKeyConfig,
     signature_pubkey: SignKeyConfig,
+    /// How calls from core are authenticated, unchecked when absent
+    #[serde(default)]
+    core_auth: Option<CoreAuth>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -71,6 +75,7 @@ pub struct Config {
     use_attr_url: bool,
     decrypter: Box<dyn JweDecrypter>,
     verifier: Box<dyn JwsVerifier>,
+    core_auth: Option<CoreAuth>,
 }
 
 // This tryfrom can be removed once try_from for fields lands in serde
@@ -84,6 +89,7 @@ impl TryFrom<RawConfig> for Config {
             use_attr_url: config.use_attr_url,
             decrypter: Box::<dyn JweDecrypter>::try_from(config.decryption_privkey)?,
             verifier: Box::<dyn JwsVerifier>::try_from(config.signature_pubkey)?,
+            core_auth: config.core_auth,
         })
     }
 }
@@ -108,4 +114,8 @@ impl Config {
     pub fn use_attr_url(&self) -> bool {
         self.use_attr_url
     }
+
+    pub fn core_auth(&self) -> Option<&CoreAuth> {
+        self.core_auth.as_ref()
+    }
 }
@@ -1,8 +1,8 @@
 use std::{error::Error as StdError, fmt::Display};
 
-use rocket::{get, launch, post, routes, serde::json::Json, State};
+use rocket::{form::Form, get, launch, post, routes, serde::json::Json, FromForm, State};
 use verder_helpen_jwt::decrypt_and_verify_auth_result;
-use verder_helpen_proto::{StartCommRequest, StartCommResponse};
+use verder_helpen_proto::{FromCore, StartCommRequest, StartCommResponse};
 
 mod config;
 
@@ -85,6 +85,25 @@ fn ui_withparams(result: &str, config: &State<Config>) -> Result<&'static str, E
     Ok(ui())
 }
 
+#[derive(FromForm)]
+struct PostedResult<'r> {
+    result: &'r str,
+}
+
+#[post("/ui", data = "<form>")]
+fn ui_form_post(
+    form: Form<PostedResult<'_>>,
+    config: &State<Config>,
+) -> Result<&'static str, Error> {
+    println!("Received posted authentication results {:?}", form.result);
+
+    let session_result =
+        decrypt_and_verify_auth_result(form.result, config.verifier(), config.decrypter())?;
+    println!("Decoded: {session_result:?}");
+
+    Ok(ui())
+}
+
 #[post("/auth_result", data = "<auth_result>")]
 fn attr_url(auth_result: &str, config: &State<Config>) -> Result<(), Error> {
     println!("Received authentication result {:?}", &auth_result);
@@ -97,10 +116,10 @@ fn attr_url(auth_result: &str, config: &State<Config>) -> Result<(), Error> {
 
 #[post("/start_communication", data = "<request>")]
 fn start(
-    request: Json<StartCommRequest>,
+    request: FromCore<StartCommRequest>,
     config: &State<Config>,
 ) -> Result<Json<StartCommResponse>, Error> {
-    println!("Received communication request {request:?}");
+    println!("Received communication request {:?}", request.0);
     if let Some(auth_result) = &request.auth_result {
         let auth_result =
             decrypt_and_verify_auth_result(auth_result, config.verifier(), config.decrypter())?;
@@ -122,11 +141,18 @@ fn start(
 
 #[launch]
 fn rocket() -> _ {
-    let base = rocket::build().mount("/", routes![start, attr_url, ui, ui_withparams,]);
+    let base = rocket::build().mount(
+        "/",
+        routes![start, attr_url, ui, ui_withparams, ui_form_post,],
+    );
     let config = base
         .figment()
         .extract::<Config>()
         .unwrap_or_else(|e| panic!("Failure to parse configuration: {e:?}"));
 
+    let base = match config.core_auth() {
+        Some(core_auth) => base.manage(core_auth.clone()),
+        None => base,
+    };
     base.manage(config)
 }
@@ -0,0 +1,62 @@
+DROP TABLE IF EXISTS "used_request_id";
+
+CREATE TABLE "used_request_id" (
+    "tenant" text NOT NULL,
+    "issuer" text NOT NULL,
+    "jti" text NOT NULL,
+    "expires_at" timestamptz NOT NULL,
+    PRIMARY KEY ("tenant", "issuer", "jti")
+);
+
+CREATE INDEX ON "used_request_id" ("expires_at");
+
+DROP TABLE IF EXISTS "stash";
+
+CREATE TABLE "stash" (
+    "tenant" text NOT NULL,
+    "kind" text NOT NULL,
+    "id" text NOT NULL,
+    "value" text NOT NULL,
+    "expires_at" timestamptz NOT NULL,
+    PRIMARY KEY ("tenant", "kind", "id")
+);
+
+CREATE INDEX ON "stash" ("expires_at");
+
+DROP TABLE IF EXISTS "session_transition";
+DROP TABLE IF EXISTS "session";
+
+CREATE TABLE "session" (
+    "id" text NOT NULL,
+    "tenant" text,
+    "purpose" text NOT NULL,
+    "auth_method" text,
+    "comm_method" text,
+    "created_at" timestamptz NOT NULL,
+    PRIMARY KEY ("id")
+);
+
+CREATE INDEX ON "session" ("created_at");
+
+CREATE TABLE "session_transition" (
+    "session_id" text NOT NULL REFERENCES "session" ("id") ON DELETE CASCADE,
+    "state" text NOT NULL,
+    "at" timestamptz NOT NULL
+);
+
+CREATE INDEX ON "session_transition" ("session_id");
+
+DROP TABLE IF EXISTS "dead_letter";
+
+CREATE TABLE "dead_letter" (
+    "id" serial NOT NULL,
+    "attr_url" text NOT NULL,
+    "result" text NOT NULL,
+    "session_id" text,
+    "request_id" text,
+    "created_at" timestamptz NOT NULL,
+    "next_attempt_at" timestamptz NOT NULL,
+    PRIMARY KEY ("id")
+);
+
+CREATE INDEX ON "dead_letter" ("next_attempt_at");
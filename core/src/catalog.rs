@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+
+use rocket::request::FromParam;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::normalize_origin,
+    error::Error,
+    methods::{AuthenticationMethod, CommunicationMethod, Method, Tag},
+};
+
+pub fn default_enabled() -> bool {
+    true
+}
+
+/// Text in several languages, keyed by language code
+pub type LocalizedText = HashMap<String, String>;
+
+/// How a method is presented to users for a specific purpose
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct MethodDisplay {
+    /// Methods are listed in ascending order, ties are broken by tag
+    #[serde(default)]
+    pub order: i32,
+    #[serde(default = "bool::default")]
+    pub recommended: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Purpose {
+    pub tag: String,
+    pub attributes: Vec<String>,
+    pub allowed_auth: Vec<String>,
+    pub allowed_comm: Vec<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub title: LocalizedText,
+    #[serde(default)]
+    pub description: LocalizedText,
+    /// Explanation of why the attributes are requested
+    #[serde(default)]
+    pub privacy_notice: LocalizedText,
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Presentation of the methods in `allowed_auth`
+    #[serde(default)]
+    pub auth_display: HashMap<Tag, MethodDisplay>,
+    /// Presentation of the methods in `allowed_comm`
+    #[serde(default)]
+    pub comm_display: HashMap<Tag, MethodDisplay>,
+    /// Auth methods to try, in order, when the plugin of a method fails to
+    /// start a session
+    #[serde(default)]
+    pub auth_fallback: HashMap<Tag, Vec<Tag>>,
+    /// Show a page listing the other auth methods when no plugin could start
+    /// a session, instead of an error
+    #[serde(default = "bool::default")]
+    pub auth_failure_page: bool,
+}
+
+pub fn contains_wildcard(target: &[String]) -> bool {
+    for val in target {
+        if val == "*" {
+            return true;
+        }
+    }
+    false
+}
+
+fn validate_methods<T>(target: &[String], options: &HashMap<String, T>) -> bool {
+    for val in target {
+        if options.get(val).is_none() {
+            return false;
+        }
+    }
+    true
+}
+
+fn validate_attributes<'a, I: Iterator<Item = &'a String>>(
+    mut attributes: I,
+    vocabulary: &[String],
+) -> bool {
+    attributes.all(|a| vocabulary.contains(a))
+}
+
+/// The kinds of entries in a catalog, as used in the admin API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogKind {
+    AuthMethods,
+    CommMethods,
+    Purposes,
+}
+
+impl CatalogKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CatalogKind::AuthMethods => "auth_methods",
+            CatalogKind::CommMethods => "comm_methods",
+            CatalogKind::Purposes => "purposes",
+        }
+    }
+}
+
+impl<'a> FromParam<'a> for CatalogKind {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        match param {
+            "auth_methods" => Ok(CatalogKind::AuthMethods),
+            "comm_methods" => Ok(CatalogKind::CommMethods),
+            "purposes" => Ok(CatalogKind::Purposes),
+            _ => Err(param),
+        }
+    }
+}
+
+/// Methods and purposes as configured, before wildcards are expanded
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CatalogEntries {
+    #[serde(default)]
+    pub auth_methods: HashMap<String, AuthenticationMethod>,
+    #[serde(default)]
+    pub comm_methods: HashMap<String, CommunicationMethod>,
+    #[serde(default)]
+    pub purposes: HashMap<String, Purpose>,
+}
+
+impl CatalogEntries {
+    // Entries in overrides replace entries with the same tag, keeping the
+    // plugin credentials of the configured entry
+    fn merge(&self, overrides: &CatalogEntries) -> CatalogEntries {
+        let mut merged = self.clone();
+        merged
+            .auth_methods
+            .extend(overrides.auth_methods.iter().map(|(tag, m)| {
+                let method = m.clone().with_credentials_of(self.auth_methods.get(tag));
+                (tag.clone(), method)
+            }));
+        merged
+            .comm_methods
+            .extend(overrides.comm_methods.iter().map(|(tag, m)| {
+                let method = m.clone().with_credentials_of(self.comm_methods.get(tag));
+                (tag.clone(), method)
+            }));
+        merged.purposes.extend(overrides.purposes.clone());
+        merged
+    }
+
+    pub fn list(&self, kind: CatalogKind) -> Result<serde_json::Value, Error> {
+        Ok(match kind {
+            CatalogKind::AuthMethods => serde_json::to_value(&self.auth_methods)?,
+            CatalogKind::CommMethods => serde_json::to_value(&self.comm_methods)?,
+            CatalogKind::Purposes => serde_json::to_value(&self.purposes)?,
+        })
+    }
+
+    pub fn get(&self, kind: CatalogKind, tag: &str) -> Result<serde_json::Value, Error> {
+        let entry = match kind {
+            CatalogKind::AuthMethods => self.auth_methods.get(tag).map(serde_json::to_value),
+            CatalogKind::CommMethods => self.comm_methods.get(tag).map(serde_json::to_value),
+            CatalogKind::Purposes => self.purposes.get(tag).map(serde_json::to_value),
+        };
+        match entry {
+            Some(entry) => Ok(entry?),
+            None if kind == CatalogKind::Purposes => Err(Error::NoSuchPurpose(tag.to_string())),
+            None => Err(Error::NoSuchMethod(tag.to_string())),
+        }
+    }
+
+    /// Add or replace an entry, which must carry the given tag
+    pub fn upsert(
+        &mut self,
+        kind: CatalogKind,
+        tag: &str,
+        entry: serde_json::Value,
+    ) -> Result<(), Error> {
+        if entry.get("tag").and_then(|t| t.as_str()) != Some(tag) {
+            return Err(Error::InvalidConfig(format!(
+                "Entry does not have tag {tag}"
+            )));
+        }
+        match kind {
+            CatalogKind::AuthMethods => {
+                self.auth_methods
+                    .insert(tag.to_string(), serde_json::from_value(entry)?);
+            }
+            CatalogKind::CommMethods => {
+                self.comm_methods
+                    .insert(tag.to_string(), serde_json::from_value(entry)?);
+            }
+            CatalogKind::Purposes => {
+                self.purposes
+                    .insert(tag.to_string(), serde_json::from_value(entry)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Methods and purposes that sessions can be started with. Disabled entries
+/// are left out, and wildcards in purposes are expanded.
+#[derive(Debug)]
+pub struct Catalog {
+    base: CatalogEntries,
+    overrides: CatalogEntries,
+    pub auth_methods: HashMap<String, AuthenticationMethod>,
+    pub comm_methods: HashMap<String, CommunicationMethod>,
+    pub purposes: HashMap<String, Purpose>,
+}
+
+impl Catalog {
+    /// Combine the configured entries with runtime overrides, checking that
+    /// the result is consistent.
+    pub fn build(
+        base: CatalogEntries,
+        overrides: CatalogEntries,
+        vocabulary: &[String],
+    ) -> Result<Catalog, String> {
+        let entries = base.merge(&overrides);
+        let mut purposes = entries.purposes;
+
+        // Handle wildcards in purpose auth and comm method lists, only
+        // including auth methods that can provide the attributes
+        for purpose in purposes.values_mut() {
+            if contains_wildcard(&purpose.allowed_auth) {
+                purpose.allowed_auth = entries
+                    .auth_methods
+                    .values()
+                    .filter(|m| m.provides(&purpose.attributes))
+                    .map(|m| m.tag().to_string())
+                    .collect();
+            }
+            if contains_wildcard(&purpose.allowed_comm) {
+                purpose.allowed_comm = entries
+                    .comm_methods
+                    .keys()
+                    .map(ToString::to_string)
+                    .collect();
+            }
+        }
+
+        // check all mentioned auth and comm methods exist
+        for purpose in purposes.values() {
+            if !validate_methods(&purpose.allowed_auth, &entries.auth_methods) {
+                return Err(format!("Invalid auth method in purpose {}", purpose.tag));
+            }
+            if !validate_methods(&purpose.allowed_comm, &entries.comm_methods) {
+                return Err(format!("Invalid comm method in purpose {}", purpose.tag));
+            }
+            if let Some(method) = purpose
+                .allowed_auth
+                .iter()
+                .filter_map(|m| entries.auth_methods.get(m))
+                .find(|m| !m.provides(&purpose.attributes))
+            {
+                return Err(format!(
+                    "Auth method {} can not provide the attributes of purpose {}",
+                    method.tag(),
+                    purpose.tag
+                ));
+            }
+            if !purpose
+                .auth_display
+                .keys()
+                .all(|m| purpose.allowed_auth.contains(m))
+            {
+                return Err(format!(
+                    "Display settings for unlisted auth method in purpose {}",
+                    purpose.tag
+                ));
+            }
+            if !purpose
+                .comm_display
+                .keys()
+                .all(|m| purpose.allowed_comm.contains(m))
+            {
+                return Err(format!(
+                    "Display settings for unlisted comm method in purpose {}",
+                    purpose.tag
+                ));
+            }
+            if !purpose
+                .auth_fallback
+                .iter()
+                .flat_map(|(method, fallbacks)| std::iter::once(method).chain(fallbacks))
+                .all(|m| purpose.allowed_auth.contains(m))
+            {
+                return Err(format!(
+                    "Fallback for or to unlisted auth method in purpose {}",
+                    purpose.tag
+                ));
+            }
+        }
+
+        for (tag, auth) in entries
+            .auth_methods
+            .iter()
+            .filter_map(|(tag, m)| Some((tag, m.plugin_auth()?)))
+            .chain(
+                entries
+                    .comm_methods
+                    .iter()
+                    .filter_map(|(tag, m)| Some((tag, m.plugin_auth()?))),
+            )
+        {
+            auth.check_secret(tag).map_err(|e| e.to_string())?;
+        }
+
+        if let Some(method) = entries.comm_methods.values().find(|m| {
+            m.allowed_redirect_origins()
+                .unwrap_or_default()
+                .iter()
+                .any(|o| normalize_origin(o).is_none())
+        }) {
+            return Err(format!(
+                "Invalid redirect origin for comm method {}",
+                method.tag()
+            ));
+        }
+
+        // check all attributes are part of the canonical vocabulary
+        if !vocabulary.is_empty() {
+            for purpose in purposes.values() {
+                if !validate_attributes(purpose.attributes.iter(), vocabulary) {
+                    return Err(format!("Unknown attribute in purpose {}", purpose.tag));
+                }
+            }
+            for method in entries.auth_methods.values() {
+                if !validate_attributes(method.attribute_mapping().keys(), vocabulary) {
+                    return Err(format!(
+                        "Unknown attribute mapped by auth method {}",
+                        method.tag()
+                    ));
+                }
+                if !validate_attributes(
+                    method.supported_attributes().unwrap_or_default().iter(),
+                    vocabulary,
+                ) {
+                    return Err(format!(
+                        "Unknown attribute supported by auth method {}",
+                        method.tag()
+                    ));
+                }
+            }
+            for method in entries.comm_methods.values() {
+                if !validate_attributes(
+                    method.allowed_attributes().unwrap_or_default().iter(),
+                    vocabulary,
+                ) {
+                    return Err(format!(
+                        "Unknown attribute allowed for comm method {}",
+                        method.tag()
+                    ));
+                }
+            }
+        }
+
+        // Leave out everything that is disabled
+        let auth_methods: HashMap<_, _> = entries
+            .auth_methods
+            .into_iter()
+            .filter(|(_, m)| m.enabled())
+            .collect();
+        let comm_methods: HashMap<_, _> = entries
+            .comm_methods
+            .into_iter()
+            .filter(|(_, m)| m.enabled())
+            .collect();
+        let purposes = purposes
+            .into_iter()
+            .filter(|(_, p)| p.enabled)
+            .map(|(tag, mut purpose)| {
+                purpose
+                    .allowed_auth
+                    .retain(|m| auth_methods.contains_key(m));
+                purpose
+                    .allowed_comm
+                    .retain(|m| comm_methods.contains_key(m));
+                (tag, purpose)
+            })
+            .collect();
+
+        Ok(Catalog {
+            base,
+            overrides,
+            auth_methods,
+            comm_methods,
+            purposes,
+        })
+    }
+
+    /// All configured entries including disabled ones, with overrides applied
+    pub fn entries(&self) -> CatalogEntries {
+        self.base.merge(&self.overrides)
+    }
+
+    pub fn base(&self) -> &CatalogEntries {
+        &self.base
+    }
+
+    pub fn overrides(&self) -> &CatalogEntries {
+        &self.overrides
+    }
+
+    pub fn purpose(&self, purpose: &str) -> Result<&Purpose, Error> {
+        self.purposes
+            .get(purpose)
+            .ok_or_else(|| Error::NoSuchPurpose(purpose.to_string()))
+    }
+
+    pub fn comm_method(
+        &self,
+        purpose: &Purpose,
+        comm_method: &str,
+    ) -> Result<&CommunicationMethod, Error> {
+        if !purpose.allowed_comm.iter().any(|c| c == comm_method) {
+            return Err(Error::NoSuchMethod(comm_method.to_string()));
+        }
+        self.comm_methods
+            .get(comm_method)
+            .ok_or_else(|| Error::NoSuchMethod(comm_method.to_string()))
+    }
+
+    pub fn auth_method(
+        &self,
+        purpose: &Purpose,
+        auth_method: &str,
+    ) -> Result<&AuthenticationMethod, Error> {
+        if !purpose.allowed_auth.iter().any(|c| c == auth_method) {
+            return Err(Error::NoSuchMethod(auth_method.to_string()));
+        }
+        self.auth_methods
+            .get(auth_method)
+            .ok_or_else(|| Error::NoSuchMethod(auth_method.to_string()))
+    }
+}
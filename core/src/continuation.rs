@@ -0,0 +1,231 @@
+use std::{collections::HashMap, time::Duration};
+
+use josekit::{
+    jws::{JwsHeader, JwsSigner},
+    jwt::{self, JwtPayload},
+};
+use reqwest::Url;
+use serde::Deserialize;
+use verder_helpen_jwt::SignKeyConfig;
+
+use crate::error::Error;
+
+fn default_ttl() -> u64 {
+    60 * 60
+}
+
+/// How continuations with a given url scheme are handed to authentication
+/// plugins
+#[derive(Debug, Deserialize)]
+pub struct RawContinuationScheme {
+    /// Wrap the continuation in a signed JWT
+    #[serde(default = "bool::default")]
+    sign: bool,
+    /// Validity of the signed continuation in seconds
+    #[serde(default = "default_ttl")]
+    ttl: u64,
+    /// Key to sign with, the UI signing key is used when absent
+    signing_privkey: Option<SignKeyConfig>,
+    /// UI endpoint the signed continuation is appended to
+    ui_endpoint: Option<String>,
+}
+
+#[derive(Debug)]
+enum ContinuationSigner {
+    Ui,
+    Own(Box<dyn JwsSigner>),
+}
+
+#[derive(Debug)]
+pub struct ContinuationScheme {
+    signer: Option<ContinuationSigner>,
+    ttl: Duration,
+    ui_endpoint: Option<String>,
+}
+
+impl ContinuationScheme {
+    pub fn from_raw(scheme: &str, raw: RawContinuationScheme, has_ui_signer: bool) -> Self {
+        let signer = match (raw.sign, raw.signing_privkey) {
+            (false, _) => None,
+            (true, Some(key)) => Some(ContinuationSigner::Own(
+                Box::<dyn JwsSigner>::try_from(key).unwrap_or_else(|e| {
+                    log::error!("Could not generate signer for continuation scheme {scheme}: {e}");
+                    panic!("Could not generate signer for continuation scheme {scheme}: {e}")
+                }),
+            )),
+            (true, None) if has_ui_signer => Some(ContinuationSigner::Ui),
+            (true, None) => {
+                log::error!("No signing key available for continuation scheme {scheme}");
+                panic!("No signing key available for continuation scheme {scheme}")
+            }
+        };
+
+        ContinuationScheme {
+            signer,
+            ttl: Duration::from_secs(raw.ttl),
+            ui_endpoint: raw.ui_endpoint,
+        }
+    }
+
+    /// UI endpoint signed continuations are appended to
+    pub fn ui_endpoint(&self) -> Option<&str> {
+        self.ui_endpoint.as_deref()
+    }
+
+    fn sign(
+        &self,
+        continuation: &str,
+        ui_signer: Option<&dyn JwsSigner>,
+    ) -> Result<Option<String>, Error> {
+        let signer = match &self.signer {
+            None => return Ok(None),
+            Some(ContinuationSigner::Ui) => ui_signer.ok_or(Error::BadConfig)?,
+            Some(ContinuationSigner::Own(signer)) => signer.as_ref(),
+        };
+
+        let mut payload = JwtPayload::new();
+        payload.set_issued_at(&std::time::SystemTime::now());
+        payload.set_expires_at(&(std::time::SystemTime::now() + self.ttl));
+        payload.set_claim("continuation", Some(serde_json::to_value(continuation)?))?;
+
+        Ok(Some(jwt::encode_with_signer(
+            &payload,
+            &JwsHeader::new(),
+            signer,
+        )?))
+    }
+}
+
+/// Schemes used when none are configured, matching the historic behaviour of
+/// passing through web urls and signing phone numbers.
+pub fn default_schemes(has_ui_signer: bool) -> HashMap<String, ContinuationScheme> {
+    let mut schemes = HashMap::new();
+    for scheme in ["http", "https"] {
+        schemes.insert(
+            scheme.to_string(),
+            ContinuationScheme {
+                signer: None,
+                ttl: Duration::from_secs(default_ttl()),
+                ui_endpoint: None,
+            },
+        );
+    }
+    if has_ui_signer {
+        schemes.insert(
+            "tel".to_string(),
+            ContinuationScheme {
+                signer: Some(ContinuationSigner::Ui),
+                ttl: Duration::from_secs(default_ttl()),
+                ui_endpoint: None,
+            },
+        );
+    }
+    schemes
+}
+
+/// Prepare a continuation for handing to an authentication plugin, according
+/// to the configuration for its scheme. Continuations with unknown schemes are
+/// rejected.
+pub fn prepare_continuation(
+    continuation: &str,
+    schemes: &HashMap<String, ContinuationScheme>,
+    ui_signer: Option<&dyn JwsSigner>,
+) -> Result<String, Error> {
+    let url = Url::parse(continuation).map_err(|_| Error::BadRequest)?;
+    let scheme = schemes.get(url.scheme()).ok_or_else(|| {
+        log::warn!("Rejected continuation with unknown scheme {}", url.scheme());
+        Error::BadRequest
+    })?;
+
+    Ok(match scheme.sign(continuation, ui_signer)? {
+        Some(token) => match &scheme.ui_endpoint {
+            Some(endpoint) => format!("{endpoint}{token}"),
+            None => token,
+        },
+        None => continuation.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+    use josekit::jws::alg::hmac::HmacJwsAlgorithm::Hs256;
+
+    use super::{default_schemes, prepare_continuation, ContinuationScheme};
+
+    #[test]
+    fn test_prepare_continuation() {
+        let raw: HashMap<String, super::RawContinuationScheme> = Figment::from(Toml::string(
+            r#"
+[https]
+
+[sip]
+sign = true
+ttl = 60
+ui_endpoint = "https://ui.example.com/sip/"
+"#,
+        ))
+        .extract()
+        .unwrap();
+        let schemes: HashMap<String, ContinuationScheme> = raw
+            .into_iter()
+            .map(|(scheme, raw)| {
+                let parsed = ContinuationScheme::from_raw(&scheme, raw, true);
+                (scheme, parsed)
+            })
+            .collect();
+        let signer = Hs256
+            .signer_from_bytes(b"sample_secret_1234567890178901237890")
+            .unwrap();
+
+        assert_eq!(
+            prepare_continuation("https://example.com/continuation", &schemes, Some(&signer))
+                .unwrap(),
+            "https://example.com/continuation"
+        );
+        let sip =
+            prepare_continuation("sip:helpdesk@example.com", &schemes, Some(&signer)).unwrap();
+        assert!(sip.starts_with("https://ui.example.com/sip/"));
+        assert_ne!(sip, "https://ui.example.com/sip/sip:helpdesk@example.com");
+
+        // Unlisted schemes are rejected rather than passed through
+        assert!(prepare_continuation("tel:0123456789", &schemes, Some(&signer)).is_err());
+        assert!(prepare_continuation("javascript:alert(1)", &schemes, Some(&signer)).is_err());
+        assert!(prepare_continuation("not a url", &schemes, Some(&signer)).is_err());
+    }
+
+    #[test]
+    fn test_default_schemes() {
+        let signer = Hs256
+            .signer_from_bytes(b"sample_secret_1234567890178901237890")
+            .unwrap();
+
+        let schemes = default_schemes(true);
+        assert_eq!(
+            prepare_continuation("http://example.com", &schemes, Some(&signer)).unwrap(),
+            "http://example.com"
+        );
+        assert_ne!(
+            prepare_continuation("tel:0123456789", &schemes, Some(&signer)).unwrap(),
+            "tel:0123456789"
+        );
+
+        // Phone numbers can not be signed without a UI signing key
+        let schemes = default_schemes(false);
+        assert!(prepare_continuation("tel:0123456789", &schemes, None).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_signed_scheme_without_key() {
+        let raw: super::RawContinuationScheme = Figment::from(Toml::string("sign = true"))
+            .extract()
+            .unwrap();
+        ContinuationScheme::from_raw("tel", raw, false);
+    }
+}
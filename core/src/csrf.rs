@@ -0,0 +1,208 @@
+use std::{collections::HashMap, time::Duration};
+
+use reqwest::Url;
+use rocket::{
+    http::{Cookie, CookieJar, Header, SameSite},
+    outcome::Outcome,
+    request::{self, FromRequest},
+    response::{self, Responder},
+    serde::json::Json,
+    Request,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::CoreConfig, error::Error};
+
+const CSRF_COOKIE: &str = "verder_helpen_csrf";
+const TOKEN_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Deserialize)]
+struct RawStartFormConfig {
+    /// Origins of pages that may post the start form, besides core itself
+    #[serde(default)]
+    allowed_origins: Vec<String>,
+    /// Whether a token issued by core has to be posted along
+    #[serde(default)]
+    csrf_tokens: bool,
+}
+
+/// Protection of the form encoded start endpoint against requests made by
+/// pages of other sites
+#[derive(Debug, Deserialize)]
+#[serde(from = "RawStartFormConfig")]
+pub struct StartFormConfig {
+    allowed_origins: Vec<String>,
+    csrf_tokens: bool,
+}
+
+fn origin_of(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()
+        .map(|url| url.origin())
+        .filter(|origin| origin.is_tuple())
+        .map(|origin| origin.ascii_serialization())
+}
+
+impl From<RawStartFormConfig> for StartFormConfig {
+    fn from(config: RawStartFormConfig) -> Self {
+        let allowed_origins = config
+            .allowed_origins
+            .iter()
+            .map(|origin| {
+                origin_of(origin).unwrap_or_else(|| {
+                    log::error!("Invalid allowed origin {origin} for the start form");
+                    panic!("Invalid allowed origin {origin} for the start form")
+                })
+            })
+            .collect();
+
+        StartFormConfig {
+            allowed_origins,
+            csrf_tokens: config.csrf_tokens,
+        }
+    }
+}
+
+impl StartFormConfig {
+    pub fn csrf_tokens(&self) -> bool {
+        self.csrf_tokens
+    }
+
+    /// Check that a form was posted by core itself or an allowed page, and
+    /// carries a token matching the cookie if those are required
+    pub fn check(
+        &self,
+        source: &FormSource,
+        token: Option<&str>,
+        config: &CoreConfig,
+    ) -> Result<(), Error> {
+        if !self.origin_allowed(source, config) {
+            log::warn!("Rejected start form posted from {:?}", source.origin);
+            return Err(Error::Forbidden("Origin is not allowed".to_owned()));
+        }
+
+        if self.csrf_tokens {
+            let nonce = token
+                .and_then(|token| config.decode_urlstate(token.to_string()).ok())
+                .and_then(|mut state| state.remove("csrf"));
+            if nonce.is_none() || nonce != source.cookie {
+                log::warn!("Rejected start form without valid csrf token");
+                return Err(Error::Forbidden("Missing or invalid csrf token".to_owned()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn origin_allowed(&self, source: &FormSource, config: &CoreConfig) -> bool {
+        source.origin.as_ref().is_some_and(|origin| {
+            self.allowed_origins.contains(origin)
+                || origin_of(config.server_url()).as_ref() == Some(origin)
+        })
+    }
+}
+
+/// Token for posting the start form, tied to a cookie set for the user
+pub fn issue_token(config: &CoreConfig, cookies: &CookieJar<'_>) -> Result<String, Error> {
+    let nonce = match cookies.get(CSRF_COOKIE) {
+        Some(cookie) => cookie.value().to_string(),
+        None => {
+            let nonce = format!("{:032x}", rand::random::<u128>());
+            // Allowed pages of other sites post the form along with the
+            // cookie, which browsers only allow for secure cookies
+            let secure = config.server_url().starts_with("https:");
+            cookies.add(
+                Cookie::build((CSRF_COOKIE, nonce.clone()))
+                    .http_only(true)
+                    .same_site(if secure {
+                        SameSite::None
+                    } else {
+                        SameSite::Lax
+                    })
+                    .secure(secure)
+                    .path("/"),
+            );
+            nonce
+        }
+    };
+
+    let mut state = HashMap::new();
+    state.insert("csrf".to_string(), nonce);
+    config.encode_urlstate_with_ttl(&state, TOKEN_TTL)
+}
+
+/// Where a posted form comes from
+pub struct FormSource {
+    /// Origin of the page, as given by the `Origin` header or else the
+    /// `Referer` header
+    origin: Option<String>,
+    /// Value of the csrf cookie
+    cookie: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for FormSource {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let headers = request.headers();
+        let origin = match headers.get_one("Origin") {
+            Some(origin) => origin_of(origin),
+            None => headers.get_one("Referer").and_then(origin_of),
+        };
+        let cookie = request
+            .cookies()
+            .get(CSRF_COOKIE)
+            .map(|cookie| cookie.value().to_string());
+        Outcome::Success(FormSource { origin, cookie })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CsrfToken {
+    csrf_token: String,
+}
+
+/// Token for the start form, readable by the allowed pages posting the form
+#[derive(Debug)]
+pub struct CsrfTokenResponse {
+    token: Json<CsrfToken>,
+    allowed_origin: Option<String>,
+}
+
+impl<'r> Responder<'r, 'static> for CsrfTokenResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = self.token.respond_to(request)?;
+        if let Some(origin) = self.allowed_origin {
+            response.set_header(Header::new("Access-Control-Allow-Origin", origin));
+            response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        }
+        response.set_header(Header::new("Vary", "Origin"));
+        response.set_header(Header::new("Cache-Control", "no-store"));
+        Ok(response)
+    }
+}
+
+/// Issue a token for posting the start form, setting the matching cookie.
+/// Responds with 404 when the start form does not require tokens.
+#[get("/start/csrf_token")]
+pub fn csrf_token(
+    source: FormSource,
+    config: &CoreConfig,
+    cookies: &CookieJar<'_>,
+) -> Result<Option<CsrfTokenResponse>, Error> {
+    let Some(start_form) = config.start_form().filter(|s| s.csrf_tokens()) else {
+        return Ok(None);
+    };
+
+    let allowed_origin = start_form
+        .origin_allowed(&source, config)
+        .then_some(source.origin)
+        .flatten();
+    Ok(Some(CsrfTokenResponse {
+        token: Json(CsrfToken {
+            csrf_token: issue_token(config, cookies)?,
+        }),
+        allowed_origin,
+    }))
+}
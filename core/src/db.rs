@@ -0,0 +1,51 @@
+use rocket::{
+    figment::Figment,
+    http::Status,
+    outcome::Outcome,
+    request::{self, FromRequest, Request},
+    Phase, Rocket,
+};
+use rocket_sync_db_pools::{database, postgres, ConnectionPool};
+
+/// Database shared between core instances, only attached when configured
+/// under `databases.core`
+#[database("core")]
+pub struct CoreDbConn(postgres::Client);
+
+/// Handle on the shared database for use outside of requests
+#[derive(Clone)]
+pub struct CoreDbPool(ConnectionPool<CoreDbConn, postgres::Client>);
+
+impl CoreDbPool {
+    pub fn from_rocket<P: Phase>(rocket: &Rocket<P>) -> Option<Self> {
+        CoreDbConn::pool(rocket).cloned().map(CoreDbPool)
+    }
+
+    pub async fn get(&self) -> Option<CoreDbConn> {
+        self.0.get().await.map(CoreDbConn)
+    }
+}
+
+pub fn database_configured(figment: &Figment) -> bool {
+    figment.find_value("databases.core").is_ok()
+}
+
+/// Connection to the shared database, if one is configured. Unlike
+/// `CoreDbConn` this guard can be used when no database is attached.
+pub struct SharedDb(pub Option<CoreDbConn>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SharedDb {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        if !database_configured(request.rocket().figment()) {
+            return Outcome::Success(SharedDb(None));
+        }
+
+        match CoreDbConn::get_one(request.rocket()).await {
+            Some(conn) => Outcome::Success(SharedDb(Some(conn))),
+            None => Outcome::Error((Status::ServiceUnavailable, ())),
+        }
+    }
+}
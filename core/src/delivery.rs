@@ -0,0 +1,457 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use rocket::{http::Status, response::Responder, Orbit, Request, Response, Rocket};
+use rocket_sync_db_pools::postgres;
+use serde::Deserialize;
+use verder_helpen_proto::REQUEST_ID_HEADER;
+
+use crate::{
+    db::{CoreDbConn, CoreDbPool},
+    error::Error,
+    page::{message_page, Texts},
+    session::{SessionRegistry, SessionState},
+};
+
+fn default_attempts() -> u32 {
+    3
+}
+
+fn default_backoff() -> u64 {
+    500
+}
+
+fn default_retry_interval() -> u64 {
+    60
+}
+
+fn default_expiry() -> u64 {
+    60 * 60
+}
+
+/// Retry behaviour for forwarding authentication results to an attribute url
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeliveryConfig {
+    /// Number of attempts made while the user waits
+    #[serde(default = "default_attempts")]
+    attempts: u32,
+    /// Delay before the first retry in milliseconds, doubled on every retry
+    #[serde(default = "default_backoff")]
+    backoff: u64,
+    /// Seconds between retries of queued deliveries
+    #[serde(default = "default_retry_interval")]
+    retry_interval: u64,
+    /// Seconds after which queued deliveries are given up on
+    #[serde(default = "default_expiry")]
+    expiry: u64,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        DeliveryConfig {
+            attempts: default_attempts(),
+            backoff: default_backoff(),
+            retry_interval: default_retry_interval(),
+            expiry: default_expiry(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeliveryFailure {
+    /// The receiving side may accept the results later on
+    Transient,
+    /// The receiving side refused the results, retrying is pointless
+    Permanent,
+}
+
+/// Authentication results that could not be delivered yet
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub attr_url: String,
+    pub result: String,
+    pub session_id: Option<String>,
+    /// ID of the request that produced the results, sent along on retries
+    pub request_id: Option<String>,
+    pub created_at: SystemTime,
+}
+
+async fn deliver_once(
+    client: &reqwest::Client,
+    attr_url: &str,
+    result: &str,
+    request_id: Option<&str>,
+) -> Result<(), DeliveryFailure> {
+    let mut request = client
+        .post(attr_url)
+        .header("Content-Type", "application/jwt")
+        .body(result.to_owned());
+    if let Some(request_id) = request_id {
+        request = request.header(REQUEST_ID_HEADER, request_id);
+    }
+    let response = request.send().await.map_err(|e| {
+        log::warn!("Could not deliver authentication result: {e}");
+        if e.is_builder() {
+            DeliveryFailure::Permanent
+        } else {
+            DeliveryFailure::Transient
+        }
+    })?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(())
+    } else if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        log::warn!("Attribute url responded with {status}, will retry");
+        Err(DeliveryFailure::Transient)
+    } else {
+        log::error!("Attribute url refused authentication result with {status}");
+        Err(DeliveryFailure::Permanent)
+    }
+}
+
+/// Forward authentication results to an attribute url, retrying transient
+/// failures with exponential backoff.
+pub async fn deliver(
+    client: &reqwest::Client,
+    attr_url: &str,
+    result: &str,
+    config: &DeliveryConfig,
+) -> Result<(), DeliveryFailure> {
+    let mut backoff = Duration::from_millis(config.backoff);
+    let mut attempt = 1;
+    loop {
+        match deliver_once(client, attr_url, result, None).await {
+            Err(DeliveryFailure::Transient) if attempt < config.attempts => {
+                rocket::tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            outcome => return outcome,
+        }
+    }
+}
+
+/// Queued result that is being retried. Results stored in the database stay
+/// there until they are delivered or given up on.
+struct ClaimedLetter {
+    id: Option<i32>,
+    letter: DeadLetter,
+}
+
+/// Queue of authentication results that could not be delivered while the
+/// user was waiting. By default the queue is kept in local memory and lost on
+/// restart; results are only persisted when a database is configured.
+#[derive(Debug, Default, Clone)]
+pub struct DeadLetterQueue {
+    letters: Arc<Mutex<Vec<DeadLetter>>>,
+}
+
+impl DeadLetterQueue {
+    pub async fn push(&self, letter: DeadLetter, db: Option<&CoreDbConn>) -> Result<(), Error> {
+        match db {
+            Some(db) => {
+                db.run(move |c| -> Result<u64, postgres::Error> {
+                    c.execute(
+                        "INSERT INTO dead_letter
+                            (attr_url, result, session_id, request_id, created_at, next_attempt_at)
+                        VALUES ($1, $2, $3, $4, $5, now())",
+                        &[
+                            &letter.attr_url,
+                            &letter.result,
+                            &letter.session_id,
+                            &letter.request_id,
+                            &letter.created_at,
+                        ],
+                    )
+                })
+                .await?;
+            }
+            None => self.letters.lock().unwrap().push(letter),
+        }
+
+        Ok(())
+    }
+
+    /// Claim the queued results that are due. Claimed results in the database
+    /// are not due again until `lease` has passed, so other instances skip
+    /// them and a crash while retrying does not lose them.
+    async fn claim(
+        &self,
+        lease: Duration,
+        db: Option<&CoreDbConn>,
+    ) -> Result<Vec<ClaimedLetter>, Error> {
+        match db {
+            Some(db) => {
+                let lease = SystemTime::now() + lease;
+                Ok(db
+                    .run(move |c| -> Result<Vec<ClaimedLetter>, postgres::Error> {
+                        Ok(c.query(
+                            "UPDATE dead_letter SET next_attempt_at = $1
+                            WHERE id IN (
+                                SELECT id FROM dead_letter
+                                WHERE next_attempt_at <= now()
+                                FOR UPDATE SKIP LOCKED
+                            )
+                            RETURNING id, attr_url, result, session_id, request_id, created_at",
+                            &[&lease],
+                        )?
+                        .iter()
+                        .map(|row| ClaimedLetter {
+                            id: Some(row.get(0)),
+                            letter: DeadLetter {
+                                attr_url: row.get(1),
+                                result: row.get(2),
+                                session_id: row.get(3),
+                                request_id: row.get(4),
+                                created_at: row.get(5),
+                            },
+                        })
+                        .collect())
+                    })
+                    .await?)
+            }
+            None => Ok(std::mem::take(&mut *self.letters.lock().unwrap())
+                .into_iter()
+                .map(|letter| ClaimedLetter { id: None, letter })
+                .collect()),
+        }
+    }
+
+    /// Return a claimed result to the queue, to be retried later
+    fn release(&self, claimed: ClaimedLetter) {
+        // Results in the database are retried once their lease has passed
+        if claimed.id.is_none() {
+            self.letters.lock().unwrap().push(claimed.letter);
+        }
+    }
+
+    /// Remove a claimed result that was delivered or given up on
+    async fn remove(&self, claimed: &ClaimedLetter, db: Option<&CoreDbConn>) -> Result<(), Error> {
+        if let (Some(id), Some(db)) = (claimed.id, db) {
+            db.run(move |c| c.execute("DELETE FROM dead_letter WHERE id = $1", &[&id]))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Try to deliver all queued results that are due once. Results that are
+    /// refused or have expired are dropped, all others are retried later.
+    pub async fn retry(
+        &self,
+        client: &reqwest::Client,
+        config: &DeliveryConfig,
+        sessions: &SessionRegistry,
+        db: Option<&CoreDbConn>,
+    ) -> Result<(), Error> {
+        let expiry = Duration::from_secs(config.expiry);
+        let lease = Duration::from_secs(config.retry_interval);
+        for claimed in self.claim(lease, db).await? {
+            let letter = &claimed.letter;
+            let request_id = letter.request_id.as_deref();
+            let delivered = deliver_once(client, &letter.attr_url, &letter.result, request_id);
+            let observed = match delivered.await {
+                Ok(()) => SessionState::AttributesDelivered,
+                Err(DeliveryFailure::Transient)
+                    if letter.created_at.elapsed().unwrap_or_default() < expiry =>
+                {
+                    self.release(claimed);
+                    continue;
+                }
+                Err(_) => {
+                    log::error!(
+                        "Giving up on delivering authentication result to {} (request {})",
+                        letter.attr_url,
+                        request_id.unwrap_or("unknown")
+                    );
+                    SessionState::AttributesFailed
+                }
+            };
+
+            self.remove(&claimed, db).await?;
+            if let Some(session_id) = &claimed.letter.session_id {
+                if let Err(e) = sessions.record(session_id, observed, db).await {
+                    log::error!("Could not record state of session {session_id}: {e}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Periodically retry queued deliveries for as long as the server runs
+pub fn spawn_retries(rocket: &Rocket<Orbit>) {
+    let (Some(queue), Some(sessions)) = (
+        rocket.state::<DeadLetterQueue>(),
+        rocket.state::<SessionRegistry>(),
+    ) else {
+        log::error!("Missing state, not retrying queued deliveries");
+        return;
+    };
+    // Queued deliveries are shared by all tenants, so retries are configured
+    // globally
+    let config = rocket
+        .figment()
+        .extract_inner::<DeliveryConfig>("delivery")
+        .unwrap_or_default();
+    let queue = queue.clone();
+    let sessions = sessions.clone();
+    let pool = CoreDbPool::from_rocket(rocket);
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!("Could not create client for retrying deliveries: {e}");
+            return;
+        }
+    };
+
+    rocket::tokio::spawn(async move {
+        loop {
+            rocket::tokio::time::sleep(Duration::from_secs(config.retry_interval)).await;
+
+            let db = match &pool {
+                Some(pool) => match pool.get().await {
+                    Some(db) => Some(db),
+                    None => {
+                        log::warn!("No database connection available to retry deliveries");
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            if let Err(e) = queue.retry(&client, &config, &sessions, db.as_ref()).await {
+                log::error!("Could not retry queued deliveries: {e}");
+            }
+        }
+    });
+}
+
+/// Error page shown to the user when their authentication results cannot be
+/// delivered, in Dutch unless the user prefers English.
+#[derive(Debug)]
+pub struct DeliveryFailedPage;
+
+impl<'r> Responder<'r, 'static> for DeliveryFailedPage {
+    fn respond_to(self, request: &'r Request<'_>) -> Result<Response<'static>, Status> {
+        let texts = Texts::for_request(request);
+        let page = message_page(
+            texts.lang(),
+            texts.get("delivery_failed", "title"),
+            &[texts.get("delivery_failed", "message")],
+        )?;
+
+        Response::build_from(page.respond_to(request)?)
+            .status(Status::BadGateway)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use httpmock::MockServer;
+
+    use super::{deliver, DeadLetter, DeadLetterQueue, DeliveryConfig, DeliveryFailure};
+    use crate::session::SessionRegistry;
+
+    fn config() -> DeliveryConfig {
+        DeliveryConfig {
+            attempts: 3,
+            backoff: 1,
+            retry_interval: 1,
+            expiry: 60,
+        }
+    }
+
+    #[test]
+    fn test_deliver_retries() {
+        let server = MockServer::start();
+        let client = reqwest::Client::new();
+
+        let unavailable = server.mock(|when, then| {
+            when.path("/unavailable").body("test");
+            then.status(503);
+        });
+        let refused = server.mock(|when, then| {
+            when.path("/refused").body("test");
+            then.status(400);
+        });
+        let accepted = server.mock(|when, then| {
+            when.path("/accepted").body("test");
+            then.status(200);
+        });
+
+        let result = tokio_test::block_on(deliver(
+            &client,
+            &server.url("/unavailable"),
+            "test",
+            &config(),
+        ));
+        assert_eq!(result, Err(DeliveryFailure::Transient));
+        unavailable.assert_hits(3);
+
+        let result =
+            tokio_test::block_on(deliver(&client, &server.url("/refused"), "test", &config()));
+        assert_eq!(result, Err(DeliveryFailure::Permanent));
+        refused.assert_hits(1);
+
+        let result = tokio_test::block_on(deliver(
+            &client,
+            &server.url("/accepted"),
+            "test",
+            &config(),
+        ));
+        assert_eq!(result, Ok(()));
+        accepted.assert_hits(1);
+    }
+
+    #[test]
+    fn test_dead_letter_retry() {
+        let server = MockServer::start();
+        let client = reqwest::Client::new();
+        let sessions = SessionRegistry::default();
+        let queue = DeadLetterQueue::default();
+
+        let unavailable = server.mock(|when, then| {
+            when.path("/unavailable");
+            then.status(503);
+        });
+        let accepted = server.mock(|when, then| {
+            when.path("/accepted")
+                .header("X-Request-Id", "test-request");
+            then.status(200);
+        });
+
+        let letter = |path: &str, created_at: SystemTime| DeadLetter {
+            attr_url: server.url(path),
+            result: "test".into(),
+            session_id: None,
+            request_id: Some("test-request".into()),
+            created_at,
+        };
+        for queued in [
+            letter("/unavailable", SystemTime::now()),
+            letter("/unavailable", SystemTime::now() - Duration::from_secs(120)),
+            letter("/accepted", SystemTime::now()),
+        ] {
+            tokio_test::block_on(queue.push(queued, None)).unwrap();
+        }
+
+        tokio_test::block_on(queue.retry(&client, &config(), &sessions, None)).unwrap();
+        unavailable.assert_hits(2);
+        accepted.assert_hits(1);
+
+        // Only the unexpired, undelivered result remains
+        let remaining = queue.letters.lock().unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].attr_url, server.url("/unavailable"));
+    }
+}
@@ -1,14 +1,42 @@
 use std::{error::Error as StdError, fmt::Display};
 
+use rocket::{
+    http::{ContentType, Status},
+    response::Responder,
+    serde::json::Json,
+    Request, Response,
+};
+use rocket_sync_db_pools::postgres;
+use serde::Serialize;
+use verder_helpen_proto::Correlation;
+
+use crate::{
+    delivery::DeliveryFailedPage,
+    page::{message_page, Texts},
+    start::AuthUnavailablePage,
+};
+
 #[derive(Debug)]
 pub enum Error {
     NoSuchMethod(String),
     NoSuchPurpose(String),
+    NoSuchSession(String),
+    MethodUnavailable(String),
     Reqwest(reqwest::Error),
     BadRequest,
+    /// Request that can not be handled, with an explanation for the client
+    InvalidRequest(String),
+    Forbidden(String),
     BadConfig,
+    InvalidConfig(String),
+    DeliveryFailed,
+    AuthUnavailable(AuthUnavailablePage),
     Jwt(josekit::JoseError),
     Json(serde_json::Error),
+    Postgres(postgres::Error),
+    Io(std::io::Error),
+    Qr(qrcode::types::QrError),
+    Png(png::EncodingError),
 }
 
 impl From<reqwest::Error> for Error {
@@ -29,28 +57,164 @@ impl From<serde_json::Error> for Error {
     }
 }
 
-impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for Error {
-    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+impl From<postgres::Error> for Error {
+    fn from(e: postgres::Error) -> Error {
+        Error::Postgres(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<qrcode::types::QrError> for Error {
+    fn from(e: qrcode::types::QrError) -> Error {
+        Error::Qr(e)
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Error {
+        Error::Png(e)
+    }
+}
+
+impl Error {
+    /// Status and stable code identifying the error to clients
+    fn problem(&self) -> (Status, &'static str) {
         match self {
-            Error::NoSuchMethod(m) => {
-                let bad_request = rocket::response::status::BadRequest::<Option<()>>(None);
-                log::error!("Unknown method {m}");
-                bad_request.respond_to(request)
-            }
-            Error::NoSuchPurpose(m) => {
-                let bad_request = rocket::response::status::BadRequest::<Option<()>>(None);
-                log::error!("Unknown purpose {m}");
-                bad_request.respond_to(request)
-            }
-            Error::BadRequest => {
-                let bad_request = rocket::response::status::BadRequest::<Option<()>>(None);
-                bad_request.respond_to(request)
+            Error::NoSuchMethod(_) => (Status::BadRequest, "no_such_method"),
+            Error::NoSuchPurpose(_) => (Status::BadRequest, "no_such_purpose"),
+            Error::NoSuchSession(_) => (Status::NotFound, "no_such_session"),
+            Error::MethodUnavailable(_) => (Status::BadRequest, "method_unavailable"),
+            Error::BadRequest => (Status::BadRequest, "bad_request"),
+            Error::InvalidRequest(_) => (Status::BadRequest, "invalid_request"),
+            Error::Forbidden(_) => (Status::Forbidden, "forbidden"),
+            Error::InvalidConfig(_) => (Status::BadRequest, "invalid_config"),
+            Error::Reqwest(_) => (Status::BadGateway, "plugin_failure"),
+            Error::DeliveryFailed => (Status::BadGateway, "delivery_failed"),
+            Error::AuthUnavailable(_) => (Status::BadGateway, "auth_unavailable"),
+            _ => (Status::InternalServerError, "internal_error"),
+        }
+    }
+
+    /// Stable code identifying the kind of error
+    pub fn code(&self) -> &'static str {
+        self.problem().1
+    }
+
+    /// Explanation safe to show to clients, which is only given for errors
+    /// whose message contains no internal details
+    fn detail(&self) -> Option<String> {
+        match self {
+            Error::NoSuchMethod(m) => Some(format!("Unknown method {m}")),
+            Error::NoSuchPurpose(m) => Some(format!("Unknown purpose {m}")),
+            Error::MethodUnavailable(m) => Some(format!("Method {m} is currently unavailable")),
+            Error::Forbidden(m) | Error::InvalidRequest(m) | Error::InvalidConfig(m) => {
+                Some(m.clone())
             }
-            _ => {
-                let debug_error = rocket::response::Debug::from(self);
-                debug_error.respond_to(request)
+            _ => None,
+        }
+    }
+
+    fn log(&self) {
+        match self {
+            Error::NoSuchMethod(m) => log::error!("Unknown method {m}"),
+            Error::NoSuchPurpose(m) => log::error!("Unknown purpose {m}"),
+            Error::NoSuchSession(m) => log::info!("Unknown session {m}"),
+            Error::MethodUnavailable(m) => log::warn!("Method {m} is currently unavailable"),
+            Error::InvalidConfig(m) => log::warn!("Rejected configuration change: {m}"),
+            Error::BadRequest | Error::InvalidRequest(_) | Error::Forbidden(_) => {}
+            Error::DeliveryFailed | Error::AuthUnavailable(_) => log::warn!("{self}"),
+            _ => log::error!("Internal error: {self:?}"),
+        }
+    }
+}
+
+/// Problem details (RFC 7807) returned to API clients
+#[derive(Debug, Serialize)]
+struct Problem {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    request_id: String,
+}
+
+// Page of the translations table with the texts of the error page shown to
+// users
+fn error_page(status: Status, code: &str) -> &'static str {
+    match code {
+        "no_such_method" | "no_such_purpose" | "bad_request" => "error_invalid_link",
+        "no_such_session" => "error_not_found",
+        "method_unavailable" => "error_unavailable",
+        "forbidden" => "error_forbidden",
+        _ if status.code < 500 => "error_invalid",
+        _ => "error_internal",
+    }
+}
+
+// API clients get problem details: those asking for json, sending json or
+// jwt bodies, or authenticating with a token. Others, like browsers posting
+// forms or following redirects, get an html page.
+fn prefers_problem_json(request: &Request<'_>) -> bool {
+    let accepts_json = request.accept().is_some_and(|accept| {
+        accept
+            .media_types()
+            .any(|media_type| media_type.is_json() || media_type.sub() == "problem+json")
+    });
+    let sends_api_body = request.content_type().is_some_and(|content_type| {
+        content_type.is_json() || content_type.media_type().sub() == "jwt"
+    });
+    accepts_json || sends_api_body || request.headers().contains("Authorization")
+}
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for Error {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'o> {
+        self.log();
+        let (status, code) = self.problem();
+        let request_id = request
+            .local_cache(|| Correlation::from_headers(request.headers()))
+            .request_id()
+            .to_string();
+
+        if !prefers_problem_json(request) {
+            match self {
+                Error::DeliveryFailed => return DeliveryFailedPage.respond_to(request),
+                Error::AuthUnavailable(page) => return page.respond_to(request),
+                _ => {}
             }
+
+            let texts = Texts::for_request(request);
+            let page = error_page(status, code);
+            let reference = format!("{}: {request_id}", texts.get("error", "reference"));
+            let page = message_page(
+                texts.lang(),
+                texts.get(page, "title"),
+                &[texts.get(page, "message"), &reference],
+            )?;
+            return Response::build_from(page.respond_to(request)?)
+                .status(status)
+                .ok();
         }
+
+        let problem = Problem {
+            problem_type: "about:blank",
+            title: status.reason().unwrap_or("Error"),
+            status: status.code,
+            code,
+            detail: self.detail(),
+            request_id,
+        };
+        Response::build_from(Json(problem).respond_to(request)?)
+            .status(status)
+            .header(ContentType::new("application", "problem+json"))
+            .ok()
     }
 }
 
@@ -59,11 +223,24 @@ impl Display for Error {
         match self {
             Error::NoSuchMethod(m) => f.write_fmt(format_args!("No such method: {m}")),
             Error::NoSuchPurpose(m) => f.write_fmt(format_args!("No such purpose: {m}")),
+            Error::NoSuchSession(m) => f.write_fmt(format_args!("No such session: {m}")),
+            Error::MethodUnavailable(m) => {
+                f.write_fmt(format_args!("Method currently unavailable: {m}"))
+            }
             Error::Reqwest(e) => e.fmt(f),
             Error::Jwt(e) => e.fmt(f),
             Error::Json(e) => e.fmt(f),
+            Error::Postgres(e) => e.fmt(f),
+            Error::Io(e) => e.fmt(f),
+            Error::Qr(e) => e.fmt(f),
+            Error::Png(e) => e.fmt(f),
             Error::BadRequest => f.write_str("Bad request"),
+            Error::InvalidRequest(m) => f.write_fmt(format_args!("Invalid request: {m}")),
+            Error::Forbidden(m) => f.write_str(m),
             Error::BadConfig => f.write_str("Bad Configuration"),
+            Error::InvalidConfig(m) => f.write_fmt(format_args!("Invalid configuration: {m}")),
+            Error::DeliveryFailed => f.write_str("Could not deliver authentication result"),
+            Error::AuthUnavailable(_) => f.write_str("Could not start any authentication method"),
         }
     }
 }
@@ -74,7 +251,121 @@ impl StdError for Error {
             Error::Reqwest(e) => Some(e),
             Error::Jwt(e) => Some(e),
             Error::Json(e) => Some(e),
+            Error::Postgres(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Qr(e) => Some(e),
+            Error::Png(e) => Some(e),
             _ => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+    use rocket::{
+        http::{Accept, ContentType, Header, Status},
+        local::blocking::Client,
+    };
+
+    use crate::setup_routes;
+
+    const TEST_CONFIG: &str = r#"
+[global]
+server_url = ""
+internal_url = ""
+internal_secret = "sample_secret_1234567890178901237890"
+authonly_request_keys = {}
+
+[[global.auth_methods]]
+tag = "test"
+name = "test"
+image_path = "none"
+start = "http://localhost:1"
+
+[[global.comm_methods]]
+tag = "test"
+name = "test"
+image_path = "none"
+start = "http://localhost:1"
+
+[[global.purposes]]
+tag = "test"
+attributes = [ "email" ]
+allowed_auth = [ "test" ]
+allowed_comm = [ "test" ]
+"#;
+
+    fn client() -> Client {
+        let figment = Figment::from(rocket::Config::default())
+            .select(rocket::Config::DEFAULT_PROFILE)
+            .merge(Toml::string(TEST_CONFIG).nested());
+        Client::tracked(setup_routes(rocket::custom(figment))).unwrap()
+    }
+
+    #[test]
+    fn test_problem_json() {
+        let client = client();
+
+        let response = client
+            .post("/start")
+            .header(ContentType::JSON)
+            .header(Accept::JSON)
+            .header(Header::new("X-Request-Id", "test-request"))
+            .body(r#"{"purpose":"unknown","auth_method":"test","comm_method":"test"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(
+            response.content_type(),
+            Some(ContentType::new("application", "problem+json"))
+        );
+        let problem = response.into_json::<serde_json::Value>().unwrap();
+        assert_eq!(problem["status"], 400);
+        assert_eq!(problem["code"], "no_such_purpose");
+        assert_eq!(problem["request_id"], "test-request");
+
+        // Internal details are not exposed
+        let response = client
+            .post("/start")
+            .header(ContentType::JSON)
+            .body(r#"{"purpose":"test","auth_method":"test","comm_method":"test"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadGateway);
+        let problem = response.into_json::<serde_json::Value>().unwrap();
+        assert_eq!(problem["code"], "plugin_failure");
+        assert!(problem.get("detail").is_none());
+    }
+
+    #[test]
+    fn test_error_page() {
+        let client = client();
+
+        let response = client
+            .post("/start")
+            .header(ContentType::Form)
+            .header(Header::new("X-Request-Id", "test-request"))
+            .body("purpose=unknown&auth_method=test&comm_method=test")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(response.content_type(), Some(ContentType::HTML));
+        let page = response.into_string().unwrap();
+        assert!(page.contains("lang=\"nl\""));
+        assert!(page.contains("Referentie: test-request"));
+        assert!(!page.contains("unknown"));
+
+        let response = client
+            .post("/start")
+            .header(ContentType::Form)
+            .header(Header::new("Accept-Language", "en"))
+            .body("purpose=test&auth_method=test&comm_method=test")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadGateway);
+        assert!(response
+            .into_string()
+            .unwrap()
+            .contains("Something went wrong on our side"));
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use rocket::{
+    http::Header,
+    response::{self, Responder},
+    Request, State,
+};
+use serde::{Deserialize, Serialize};
+use tera::Context;
+
+use crate::{
+    config::CoreConfig,
+    db::{CoreDbConn, SharedDb},
+    error::Error,
+    page::{render_page, Texts},
+    stash::Stash,
+};
+
+/// Time the user has to open the page posting a result
+const FORM_POST_TTL: Duration = Duration::from_secs(30 * 60);
+/// Kind of stashed results
+const FORM_POST_KIND: &str = "form_post";
+
+/// Result waiting to be posted to a plugin
+#[derive(Debug, Serialize, Deserialize)]
+struct StashedFormPost {
+    action: String,
+    result: String,
+}
+
+/// Page handing an authentication result to a plugin with an automatically
+/// submitted form, so the result does not end up in urls
+pub struct FormPostPage {
+    action: String,
+    result: String,
+}
+
+impl FormPostPage {
+    pub fn new(action: &str, result: &str) -> Self {
+        FormPostPage {
+            action: action.to_string(),
+            result: result.to_string(),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for FormPostPage {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let texts = Texts::for_request(request);
+        let mut context = Context::new();
+        context.insert("action", &self.action);
+        context.insert("result", &self.result);
+        let mut response = render_page(
+            "form_post.html",
+            texts.lang(),
+            texts.get("form_post", "title"),
+            context,
+        )?
+        .respond_to(request)?;
+        // The page contains the result, so it should not be kept around
+        response.set_header(Header::new("Cache-Control", "no-store"));
+        Ok(response)
+    }
+}
+
+/// Url of a page in core posting the result to the given url. The result is
+/// kept server side under a random ID until the page is served once, so it
+/// does not end up in urls either.
+pub async fn form_post_url(
+    config: &CoreConfig,
+    action: &str,
+    result: &str,
+    stash: &Stash,
+    db: Option<&CoreDbConn>,
+) -> Result<String, Error> {
+    let form_post = serde_json::to_string(&StashedFormPost {
+        action: action.to_owned(),
+        result: result.to_owned(),
+    })?;
+    let id = stash
+        .put(
+            config.tenant(),
+            FORM_POST_KIND,
+            form_post,
+            FORM_POST_TTL,
+            db,
+        )
+        .await?;
+    Ok(format!("{}/form_post/{id}", config.server_url()))
+}
+
+#[get("/form_post/<id>")]
+pub async fn form_post_page(
+    id: &str,
+    config: &CoreConfig,
+    stash: &State<Stash>,
+    db: SharedDb,
+) -> Result<FormPostPage, Error> {
+    let form_post = stash
+        .take(config.tenant(), FORM_POST_KIND, id, db.0.as_ref())
+        .await?
+        .ok_or(Error::BadRequest)?;
+    let StashedFormPost { action, result } = serde_json::from_str(&form_post)?;
+    Ok(FormPostPage::new(&action, &result))
+}
@@ -0,0 +1,378 @@
+use std::time::Duration;
+
+use qrcode::{render::svg, Color, QrCode};
+use rocket::{
+    http::{ContentType, Status},
+    outcome::Outcome,
+    request::{self, FromRequest},
+    response::Redirect,
+    Request, State,
+};
+use serde::{Deserialize, Serialize};
+use verder_helpen_proto::Handoff;
+
+use crate::{
+    config::CoreConfig,
+    db::{CoreDbConn, SharedDb},
+    error::Error,
+    session::{SessionRegistry, SessionState},
+    stash::Stash,
+};
+
+/// Validity of hand-off urls
+const HANDOFF_TTL: Duration = Duration::from_secs(10 * 60);
+/// Kind of stashed hand-offs
+const HANDOFF_KIND: &str = "handoff";
+/// Size in pixels of a single module in PNG QR codes
+const PNG_SCALE: usize = 8;
+/// Width in modules of the white border around PNG QR codes
+const QUIET_ZONE: usize = 4;
+
+/// Session to continue on another device
+#[derive(Debug, Serialize, Deserialize)]
+struct StashedHandoff {
+    client_url: String,
+    session_id: Option<String>,
+}
+
+/// Whether urls for continuing on another device were requested, through the
+/// `handoff` query parameter
+pub struct HandoffRequest<'r> {
+    requested: bool,
+    stash: &'r Stash,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for HandoffRequest<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let Some(stash) = request.rocket().state::<Stash>() else {
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+        let requested = request
+            .query_value::<bool>("handoff")
+            .and_then(Result::ok)
+            .unwrap_or(false);
+        Outcome::Success(HandoffRequest { requested, stash })
+    }
+}
+
+impl HandoffRequest<'_> {
+    /// Hand-off urls for a started session, when requested
+    pub async fn urls(
+        &self,
+        config: &CoreConfig,
+        client_url: &str,
+        session_id: Option<&str>,
+        db: Option<&CoreDbConn>,
+    ) -> Result<Option<Handoff>, Error> {
+        if !self.requested {
+            return Ok(None);
+        }
+        let urls = handoff_urls(config, client_url, session_id, self.stash, db).await?;
+        Ok(Some(urls))
+    }
+}
+
+/// Create the urls through which the session at `client_url` can be
+/// continued on another device. The client url is kept server side under a
+/// short random ID, so it does not leak through QR codes or logs and the QR
+/// codes stay small enough to scan.
+async fn handoff_urls(
+    config: &CoreConfig,
+    client_url: &str,
+    session_id: Option<&str>,
+    stash: &Stash,
+    db: Option<&CoreDbConn>,
+) -> Result<Handoff, Error> {
+    let handoff = serde_json::to_string(&StashedHandoff {
+        client_url: client_url.to_owned(),
+        session_id: session_id.map(str::to_owned),
+    })?;
+    let id = stash
+        .put(config.tenant(), HANDOFF_KIND, handoff, HANDOFF_TTL, db)
+        .await?;
+
+    let url = format!("{}/handoff/{id}", config.server_url());
+    Ok(Handoff {
+        qr_svg: format!("{url}/qr.svg"),
+        qr_png: format!("{url}/qr.png"),
+        url,
+    })
+}
+
+// Hand-off stored under an ID
+async fn find_handoff(
+    config: &CoreConfig,
+    id: &str,
+    stash: &Stash,
+    db: Option<&CoreDbConn>,
+) -> Result<StashedHandoff, Error> {
+    let handoff = stash
+        .get(config.tenant(), HANDOFF_KIND, id, db)
+        .await?
+        .ok_or(Error::BadRequest)?;
+    Ok(serde_json::from_str(&handoff)?)
+}
+
+// QR code for the hand-off url of an ID, after checking the hand-off exists
+async fn handoff_qr(
+    config: &CoreConfig,
+    id: &str,
+    stash: &Stash,
+    db: Option<&CoreDbConn>,
+) -> Result<QrCode, Error> {
+    find_handoff(config, id, stash, db).await?;
+    Ok(QrCode::new(format!(
+        "{}/handoff/{id}",
+        config.server_url()
+    ))?)
+}
+
+fn render_png(code: &QrCode) -> Result<Vec<u8>, Error> {
+    let width = code.width();
+    let size = (width + 2 * QUIET_ZONE) * PNG_SCALE;
+    let mut pixels = vec![255u8; size * size];
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            let x = (i % width + QUIET_ZONE) * PNG_SCALE;
+            let y = (i / width + QUIET_ZONE) * PNG_SCALE;
+            for row in y..y + PNG_SCALE {
+                pixels[row * size + x..row * size + x + PNG_SCALE].fill(0);
+            }
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(png)
+}
+
+/// Continue a session on the device that opened the hand-off url. The
+/// session is marked as handed off, so the original device can notice
+/// through `/session/<id>`. A hand-off url can be used only once.
+#[get("/handoff/<id>")]
+pub async fn handoff_continue(
+    id: &str,
+    config: &CoreConfig,
+    stash: &State<Stash>,
+    sessions: &State<SessionRegistry>,
+    db: SharedDb,
+) -> Result<Redirect, Error> {
+    let db = db.0.as_ref();
+    let handoff = stash
+        .take(config.tenant(), HANDOFF_KIND, id, db)
+        .await?
+        .ok_or(Error::BadRequest)?;
+    let StashedHandoff {
+        client_url,
+        session_id,
+    } = serde_json::from_str(&handoff)?;
+
+    if let Some(session_id) = session_id {
+        let started = sessions
+            .get(&session_id, db)
+            .await?
+            .filter(|session| session.tenant.as_deref() == config.tenant())
+            .is_some_and(|session| session.status() == SessionState::Started);
+        if started {
+            sessions
+                .record(&session_id, SessionState::HandedOff, db)
+                .await?;
+        }
+    }
+
+    Ok(Redirect::to(client_url))
+}
+
+#[get("/handoff/<id>/qr.svg")]
+pub async fn handoff_qr_svg(
+    id: &str,
+    config: &CoreConfig,
+    stash: &State<Stash>,
+    db: SharedDb,
+) -> Result<(ContentType, String), Error> {
+    let code = handoff_qr(config, id, stash, db.0.as_ref()).await?;
+    Ok((
+        ContentType::SVG,
+        code.render::<svg::Color>().min_dimensions(200, 200).build(),
+    ))
+}
+
+#[get("/handoff/<id>/qr.png")]
+pub async fn handoff_qr_png(
+    id: &str,
+    config: &CoreConfig,
+    stash: &State<Stash>,
+    db: SharedDb,
+) -> Result<(ContentType, Vec<u8>), Error> {
+    let code = handoff_qr(config, id, stash, db.0.as_ref()).await?;
+    Ok((ContentType::PNG, render_png(&code)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+    use rocket::{
+        http::{Accept, ContentType, Status},
+        local::blocking::Client,
+    };
+    use serde_json::json;
+    use verder_helpen_proto::ClientUrlResponse;
+
+    use crate::setup_routes;
+
+    fn handoff_client(server: &httpmock::MockServer) -> Client {
+        let figment = Figment::from(rocket::Config::default())
+            .select(rocket::Config::DEFAULT_PROFILE)
+            .merge(
+                Toml::string(&format!(
+                    r#"
+[global]
+server_url = "https://core.example.com"
+internal_url = ""
+internal_secret = "sample_secret_1234567890178901237890"
+authonly_request_keys = {{}}
+track_sessions = true
+
+[[global.auth_methods]]
+tag = "test"
+name = "test"
+image_path = "none"
+start = "{0}"
+
+[[global.comm_methods]]
+tag = "test"
+name = "test"
+image_path = "none"
+start = "{0}"
+
+[[global.purposes]]
+tag = "test"
+attributes = [ "email" ]
+allowed_auth = [ "test" ]
+allowed_comm = [ "test" ]
+"#,
+                    server.base_url(),
+                ))
+                .nested(),
+            );
+        Client::tracked(setup_routes(rocket::custom(figment))).unwrap()
+    }
+
+    fn start_session(
+        client: &Client,
+        server: &httpmock::MockServer,
+        uri: &str,
+    ) -> ClientUrlResponse {
+        server.mock(|when, then| {
+            when.path("/start_communication");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({
+                    "client_url": "https://example.com/continuation",
+                }));
+        });
+        server.mock(|when, then| {
+            when.path("/start_authentication");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({
+                    "client_url": "https://example.com/auth",
+                }));
+        });
+
+        let response = client
+            .post(uri.to_string())
+            .header(ContentType::JSON)
+            .header(Accept::JSON)
+            .body(r#"{"purpose":"test","auth_method":"test","comm_method":"test"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json::<ClientUrlResponse>().unwrap()
+    }
+
+    fn session_status(client: &Client, session_id: &str) -> serde_json::Value {
+        let response = client.get(format!("/session/{session_id}")).dispatch();
+        response.into_json::<serde_json::Value>().unwrap()["status"].clone()
+    }
+
+    #[test]
+    fn test_handoff() {
+        let server = httpmock::MockServer::start();
+        let client = handoff_client(&server);
+
+        let body = start_session(&client, &server, "/start?handoff=true");
+        let handoff = body.handoff.unwrap();
+        let session_id = body.session_id.unwrap();
+        let path = handoff
+            .url
+            .strip_prefix("https://core.example.com")
+            .unwrap();
+        assert_eq!(handoff.qr_svg, format!("{}/qr.svg", handoff.url));
+        // Only a short ID ends up in the QR code
+        assert!(path.len() < 50);
+        assert_eq!(handoff.qr_png, format!("{}/qr.png", handoff.url));
+
+        let response = client.get(format!("{path}/qr.svg")).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::SVG));
+        assert!(response.into_string().unwrap().contains("<svg"));
+
+        let response = client.get(format!("{path}/qr.png")).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+        assert!(response
+            .into_bytes()
+            .unwrap()
+            .starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(session_status(&client, &session_id), "started");
+
+        let response = client.get(path.to_string()).dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some("https://example.com/auth")
+        );
+        assert_eq!(session_status(&client, &session_id), "handed_off");
+
+        // The hand-off can not be replayed, also not through its QR codes
+        let response = client.get(path.to_string()).dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client.get(format!("{path}/qr.svg")).dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_start_without_handoff() {
+        let server = httpmock::MockServer::start();
+        let client = handoff_client(&server);
+
+        let body = start_session(&client, &server, "/start");
+        assert!(body.handoff.is_none());
+    }
+
+    #[test]
+    fn test_handoff_invalid_token() {
+        let server = httpmock::MockServer::start();
+        let client = handoff_client(&server);
+
+        for path in [
+            "/handoff/invalid",
+            "/handoff/invalid/qr.svg",
+            "/handoff/invalid/qr.png",
+        ] {
+            let response = client.get(path).dispatch();
+            assert_ne!(response.status(), Status::Ok);
+        }
+    }
+}
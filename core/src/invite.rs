@@ -0,0 +1,476 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Utc};
+use rocket::{
+    form::Form, http::Status, response::Responder, serde::json::Json, Request, Response, State,
+};
+use serde::{Deserialize, Serialize};
+use verder_helpen_proto::Correlation;
+
+use crate::{
+    admin::Admin,
+    config::CoreConfig,
+    db::SharedDb,
+    error::Error,
+    methods::Tag,
+    page::{methods_page, Texts},
+    replay::{Issuer, ReplayStore},
+    session::SessionRegistry,
+    start::{offered_auth_methods, session_start_full, ClientUrlResponse, StartRequestFull},
+};
+
+fn default_invitation_ttl() -> u64 {
+    7 * 24 * 60 * 60
+}
+
+/// Longest validity of invitations in seconds
+const MAX_INVITATION_TTL: u64 = 90 * 24 * 60 * 60;
+
+/// Request for an invitation link starting a session with a fixed purpose
+/// and communication method
+#[derive(Debug, Deserialize)]
+pub struct InvitationRequest {
+    purpose: String,
+    comm_method: Tag,
+    /// Validity of the invitation in seconds, at most 90 days
+    #[serde(default = "default_invitation_ttl")]
+    ttl: u64,
+    /// Whether the invitation can only be used to start a single session
+    #[serde(default)]
+    single_use: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Invitation {
+    token: String,
+    url: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Contents of an invitation token
+#[derive(Debug)]
+struct InvitationClaims {
+    purpose: String,
+    comm_method: Tag,
+    /// ID of single-use invitations, with the time until which it is valid
+    jti: Option<(String, SystemTime)>,
+}
+
+impl InvitationClaims {
+    fn decode(config: &CoreConfig, token: String) -> Result<Self, Error> {
+        let mut state = config
+            .decode_invitation(token)
+            .map_err(|_| Error::BadRequest)?;
+        let purpose = state.remove("invite_purpose").ok_or(Error::BadRequest)?;
+        let comm_method = state
+            .remove("invite_comm_method")
+            .ok_or(Error::BadRequest)?;
+        let jti = match (
+            state.remove("invite_jti"),
+            state.remove("invite_expires_at"),
+        ) {
+            (Some(jti), Some(expires_at)) => {
+                let expires_at = expires_at.parse().map_err(|_| Error::BadRequest)?;
+                Some((jti, UNIX_EPOCH + Duration::from_secs(expires_at)))
+            }
+            _ => None,
+        };
+
+        Ok(InvitationClaims {
+            purpose,
+            comm_method,
+            jti,
+        })
+    }
+}
+
+/// Create an invitation link, for example to send to a citizen by email or
+/// letter. The purpose and communication method are checked, but may still
+/// be removed or disabled before the invitation is used.
+#[post("/admin/invitations", format = "application/json", data = "<request>")]
+pub fn create_invitation(
+    request: Json<InvitationRequest>,
+    config: &CoreConfig,
+    _admin: Admin,
+) -> Result<Json<Invitation>, Error> {
+    let catalog = config.catalog();
+    let purpose = catalog.purpose(&request.purpose)?;
+    catalog.comm_method(purpose, &request.comm_method)?;
+
+    if request.ttl > MAX_INVITATION_TTL {
+        return Err(Error::InvalidRequest(format!(
+            "Invitations can be valid for at most {MAX_INVITATION_TTL} seconds"
+        )));
+    }
+    let ttl = Duration::from_secs(request.ttl);
+    let expires_at = SystemTime::now()
+        .checked_add(ttl)
+        .ok_or(Error::BadRequest)?;
+    let mut state = HashMap::new();
+    state.insert("invite_purpose".to_string(), request.purpose.clone());
+    state.insert(
+        "invite_comm_method".to_string(),
+        request.comm_method.clone(),
+    );
+    if request.single_use {
+        state.insert(
+            "invite_jti".to_string(),
+            format!("{:032x}", rand::random::<u128>()),
+        );
+        state.insert(
+            "invite_expires_at".to_string(),
+            expires_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                .to_string(),
+        );
+    }
+    let token = config.encode_invitation(&state, ttl)?;
+    log::info!(
+        "Audit: admin created invitation for purpose {} with {}",
+        request.purpose,
+        request.comm_method
+    );
+
+    Ok(Json(Invitation {
+        url: format!("{}/invite/{token}", config.server_url()),
+        token,
+        expires_at: expires_at.into(),
+    }))
+}
+
+/// Page listing the auth methods a citizen can use to accept an invitation
+#[derive(Debug)]
+pub struct InvitationPage {
+    token: String,
+    methods: Vec<(Tag, String)>,
+}
+
+impl<'r> Responder<'r, 'static> for InvitationPage {
+    fn respond_to(self, request: &'r Request<'_>) -> Result<Response<'static>, Status> {
+        let texts = Texts::for_request(request);
+
+        // Forms post back to the url of the page, /invite/<token>
+        methods_page(
+            texts.lang(),
+            texts.get("invitation", "title"),
+            [
+                texts.get("invitation", "message"),
+                texts.get("invitation", "none_left"),
+            ],
+            &self.token,
+            &[],
+            &self.methods,
+        )?
+        .respond_to(request)
+    }
+}
+
+#[get("/invite/<token>")]
+pub fn invitation(token: String, config: &CoreConfig) -> Result<InvitationPage, Error> {
+    let claims = InvitationClaims::decode(config, token.clone())?;
+    let catalog = config.catalog();
+    let purpose = catalog.purpose(&claims.purpose)?;
+
+    Ok(InvitationPage {
+        methods: offered_auth_methods(&catalog, purpose, &[]),
+        token,
+    })
+}
+
+#[derive(Debug, FromForm)]
+pub struct InvitationChoice {
+    auth_method: Tag,
+}
+
+#[post(
+    "/invite/<token>",
+    format = "application/x-www-form-urlencoded",
+    data = "<choice>"
+)]
+pub async fn accept_invitation(
+    token: String,
+    choice: Form<InvitationChoice>,
+    config: &CoreConfig,
+    replay_store: &State<ReplayStore>,
+    sessions: &State<SessionRegistry>,
+    db: SharedDb,
+    correlation: &Correlation,
+) -> Result<ClientUrlResponse, Error> {
+    let claims = InvitationClaims::decode(config, token)?;
+    let db = db.0.as_ref();
+
+    // Reserved before starting, so concurrent or replayed requests can not
+    // start a second session
+    if let Some((jti, expires_at)) = &claims.jti {
+        replay_store
+            .register(config.tenant(), Issuer::Core, jti, *expires_at, db)
+            .await?;
+    }
+
+    let response = session_start_full(
+        StartRequestFull {
+            purpose: claims.purpose,
+            auth_method: choice.into_inner().auth_method,
+            comm_method: claims.comm_method,
+        },
+        config,
+        sessions,
+        db,
+        correlation,
+    )
+    .await;
+
+    // A method that is unavailable does not use up the invitation
+    if let (Err(_), Some((jti, _))) = (&response, &claims.jti) {
+        replay_store
+            .release(config.tenant(), Issuer::Core, jti, db)
+            .await?;
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+    use rocket::{
+        http::{ContentType, Header, Status},
+        local::blocking::Client,
+    };
+    use serde_json::json;
+
+    use super::Invitation;
+    use crate::setup_routes;
+
+    fn invite_client(server: &httpmock::MockServer) -> Client {
+        let figment = Figment::from(rocket::Config::default())
+            .select(rocket::Config::DEFAULT_PROFILE)
+            .merge(
+                Toml::string(&format!(
+                    r#"
+[global]
+server_url = "https://core.example.com"
+internal_url = ""
+internal_secret = "sample_secret_1234567890178901237890"
+authonly_request_keys = {{}}
+
+[global.admin]
+token = "admin_token_1234567890"
+
+[[global.auth_methods]]
+tag = "digid"
+name = "DigiD"
+image_path = "none"
+start = "{0}/digid"
+
+[[global.auth_methods]]
+tag = "irma"
+name = "IRMA"
+image_path = "none"
+start = "{0}/irma"
+
+[[global.comm_methods]]
+tag = "call"
+name = "Call"
+image_path = "none"
+start = "{0}"
+
+[[global.purposes]]
+tag = "permit"
+attributes = [ "email" ]
+allowed_auth = [ "digid", "irma" ]
+allowed_comm = [ "call" ]
+auth_display = {{ irma = {{ order = 1 }}, digid = {{ order = 2 }} }}
+"#,
+                    server.base_url(),
+                ))
+                .nested(),
+            );
+        Client::tracked(setup_routes(rocket::custom(figment))).unwrap()
+    }
+
+    fn create_invitation(client: &Client, request: serde_json::Value) -> Invitation {
+        let response = client
+            .post("/admin/invitations")
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                "Bearer admin_token_1234567890",
+            ))
+            .body(request.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json::<Invitation>().unwrap()
+    }
+
+    fn accept(client: &Client, token: &str, auth_method: &str) -> Status {
+        client
+            .post(format!("/invite/{token}"))
+            .header(ContentType::Form)
+            .body(format!("auth_method={auth_method}"))
+            .dispatch()
+            .status()
+    }
+
+    #[test]
+    fn test_invitation() {
+        let server = httpmock::MockServer::start();
+        let client = invite_client(&server);
+        let comm_mock = server.mock(|when, then| {
+            when.path("/start_communication");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({
+                    "client_url": "https://example.com/continuation",
+                }));
+        });
+        let irma_mock = server.mock(|when, then| {
+            when.path("/irma/start_authentication");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({
+                    "client_url": "https://example.com/irma",
+                }));
+        });
+
+        let invitation = create_invitation(
+            &client,
+            json!({ "purpose": "permit", "comm_method": "call" }),
+        );
+        assert_eq!(
+            invitation.url,
+            format!("https://core.example.com/invite/{}", invitation.token)
+        );
+
+        let response = client
+            .get(format!("/invite/{}", invitation.token))
+            .header(Header::new("Accept-Language", "en"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let page = response.into_string().unwrap();
+        assert!(page.contains("Choose how you want to log in"));
+        assert!(page.find("IRMA").unwrap() < page.find("DigiD").unwrap());
+
+        let response = client
+            .post(format!("/invite/{}", invitation.token))
+            .header(ContentType::Form)
+            .body("auth_method=irma")
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some("https://example.com/irma")
+        );
+        comm_mock.assert();
+        irma_mock.assert();
+
+        // Invitations can be used multiple times unless single-use
+        assert_eq!(accept(&client, &invitation.token, "irma"), Status::SeeOther);
+    }
+
+    #[test]
+    fn test_invitation_single_use() {
+        let server = httpmock::MockServer::start();
+        let client = invite_client(&server);
+        server.mock(|when, then| {
+            when.path("/start_communication");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({
+                    "client_url": "https://example.com/continuation",
+                }));
+        });
+        let digid_mock = server.mock(|when, then| {
+            when.path("/digid/start_authentication");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({
+                    "client_url": "https://example.com/digid",
+                }));
+        });
+
+        let invitation = create_invitation(
+            &client,
+            json!({ "purpose": "permit", "comm_method": "call", "single_use": true }),
+        );
+        // Failing to start a session does not use up the invitation
+        assert_ne!(
+            accept(&client, &invitation.token, "eherkenning"),
+            Status::SeeOther
+        );
+        assert_eq!(
+            accept(&client, &invitation.token, "digid"),
+            Status::SeeOther
+        );
+        // The same token posted again is refused before any plugin is called
+        assert_eq!(
+            accept(&client, &invitation.token, "digid"),
+            Status::Forbidden
+        );
+        digid_mock.assert_hits(1);
+    }
+
+    #[test]
+    fn test_invitation_invalid() {
+        let server = httpmock::MockServer::start();
+        let client = invite_client(&server);
+
+        // Creating invitations requires the admin token
+        let response = client
+            .post("/admin/invitations")
+            .header(ContentType::JSON)
+            .body(json!({ "purpose": "permit", "comm_method": "call" }).to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        // Purpose and communication method must exist
+        let response = client
+            .post("/admin/invitations")
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                "Bearer admin_token_1234567890",
+            ))
+            .body(json!({ "purpose": "permit", "comm_method": "chat" }).to_string())
+            .dispatch();
+        assert_ne!(response.status(), Status::Ok);
+
+        // Validity is limited
+        let response = client
+            .post("/admin/invitations")
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                "Bearer admin_token_1234567890",
+            ))
+            .body(
+                json!({ "purpose": "permit", "comm_method": "call", "ttl": u64::MAX }).to_string(),
+            )
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let problem = response.into_json::<serde_json::Value>().unwrap();
+        assert_eq!(problem["code"], "invalid_request");
+
+        let response = client.get("/invite/invalid").dispatch();
+        assert_ne!(response.status(), Status::Ok);
+        assert_ne!(accept(&client, "invalid", "digid"), Status::SeeOther);
+
+        // Only auth methods of the purpose can be chosen
+        let invitation = create_invitation(
+            &client,
+            json!({ "purpose": "permit", "comm_method": "call" }),
+        );
+        assert_ne!(
+            accept(&client, &invitation.token, "eherkenning"),
+            Status::SeeOther
+        );
+    }
+}
@@ -1,44 +1,117 @@
+mod admin;
+mod catalog;
 mod config;
+mod continuation;
+mod csrf;
+mod db;
+mod delivery;
 mod error;
+mod form_post;
+mod handoff;
+mod invite;
 mod methods;
 mod options;
+mod page;
+mod reencrypt;
+mod replay;
+mod select;
+mod session;
 mod start;
+mod stash;
+mod tenant;
+mod webhook;
 
 #[macro_use]
 extern crate rocket;
 
-use config::CoreConfig;
-use methods::auth_attr_shim;
+use admin::{admin_disable, admin_enable, admin_get, admin_list, admin_put};
+use csrf::csrf_token;
+use db::{database_configured, CoreDbConn};
+use delivery::{spawn_retries, DeadLetterQueue};
+use form_post::form_post_page;
+use handoff::{handoff_continue, handoff_qr_png, handoff_qr_svg};
+use invite::{accept_invitation, create_invitation, invitation};
+use methods::{auth_attr_shim, auth_attr_shim_form};
 use options::{all_session_options, session_options};
+use replay::ReplayStore;
 use rocket::{fairing::AdHoc, Build};
+use select::auth_select;
+use session::{session_status, SessionRegistry};
 use start::{session_start, session_start_form, session_start_jwt};
+use stash::Stash;
+use tenant::{tenant_path_prefixes, Tenants};
+use verder_helpen_proto::CorrelationFairing;
 
 #[launch]
 fn boot() -> _ {
-    #[allow(unused_mut)]
     let mut base = setup_routes(rocket::build());
 
     #[allow(unused_variables)]
-    let config = base.figment().extract::<CoreConfig>().unwrap_or_else(|_| {
+    let config = Tenants::extract(base.figment()).unwrap_or_else(|_| {
         // Ignore error value, as it could contain private keys
         log::error!("Failure to parse configuration");
         panic!("Failure to parse configuration")
     });
 
+    // A database is only needed when running multiple core instances
+    if database_configured(base.figment()) {
+        base = base.attach(CoreDbConn::fairing());
+    }
+
     base
 }
 
-fn setup_routes(base: rocket::Rocket<Build>) -> rocket::Rocket<Build> {
-    base.mount(
-        "/",
-        routes![
-            all_session_options,
-            session_options,
-            session_start,
-            session_start_form,
-            session_start_jwt,
-            auth_attr_shim,
-        ],
-    )
-    .attach(AdHoc::config::<CoreConfig>())
+fn setup_routes(mut base: rocket::Rocket<Build>) -> rocket::Rocket<Build> {
+    // Tenants served under a path prefix get their own copy of all routes
+    let prefixes = tenant_path_prefixes(base.figment());
+    for prefix in std::iter::once("/".to_string()).chain(prefixes) {
+        base = base.mount(
+            prefix,
+            routes![
+                all_session_options,
+                session_options,
+                auth_select,
+                session_start,
+                session_start_form,
+                session_start_jwt,
+                csrf_token,
+                auth_attr_shim,
+                auth_attr_shim_form,
+                form_post_page,
+                session_status,
+                handoff_continue,
+                handoff_qr_svg,
+                handoff_qr_png,
+                admin_list,
+                admin_get,
+                admin_put,
+                admin_enable,
+                admin_disable,
+                create_invitation,
+                invitation,
+                accept_invitation,
+            ],
+        );
+    }
+
+    base.manage(ReplayStore::default())
+        .manage(SessionRegistry::default())
+        .manage(Stash::default())
+        .manage(DeadLetterQueue::default())
+        .attach(AdHoc::try_on_ignite("Tenants", |rocket| {
+            Box::pin(async move {
+                match Tenants::extract(rocket.figment()) {
+                    Ok(tenants) => Ok(rocket.manage(tenants)),
+                    Err(_) => {
+                        // Ignore error value, as it could contain private keys
+                        log::error!("Failure to parse configuration");
+                        Err(rocket)
+                    }
+                }
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Delivery retries", |rocket| {
+            Box::pin(async move { spawn_retries(rocket) })
+        }))
+        .attach(CorrelationFairing)
 }
@@ -1,13 +1,80 @@
 mod auth;
+mod availability;
 mod comm;
+mod plugin_auth;
 
-pub use auth::{auth_attr_shim, AuthenticationMethod};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use serde::Serialize;
+use verder_helpen_proto::Correlation;
+
+pub use auth::{auth_attr_shim, auth_attr_shim_form, AuthenticationMethod, ResultDestination};
+pub use availability::Availability;
 pub use comm::CommunicationMethod;
+pub use plugin_auth::PluginAuth;
+
+use crate::error::Error;
 
 pub type Tag = String;
 
+/// Client for calls to plugins, passing on the identification of the request
+/// being handled
+pub fn http_client(correlation: &Correlation) -> Result<reqwest::Client, reqwest::Error> {
+    client_builder(correlation).build()
+}
+
+fn client_builder(correlation: &Correlation) -> reqwest::ClientBuilder {
+    let mut headers = HeaderMap::new();
+    for (name, value) in correlation.headers() {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .default_headers(headers)
+}
+
+/// Post a JSON body to a plugin, authenticated with the credentials
+/// configured for its method
+pub async fn post_to_plugin<T: Serialize>(
+    url: &str,
+    body: &T,
+    auth: Option<&PluginAuth>,
+    correlation: &Correlation,
+) -> Result<reqwest::Response, Error> {
+    let body = serde_json::to_string(body)?;
+    let mut builder = client_builder(correlation);
+    if let Some(auth) = auth {
+        builder = auth.configure(builder)?;
+    }
+    let mut request = builder
+        .build()?
+        .post(url)
+        .header(CONTENT_TYPE, "application/json");
+    if let Some(auth) = auth {
+        request = auth.authenticate(request, &body)?;
+    }
+    Ok(request.body(body).send().await?)
+}
+
 pub trait Method {
     fn tag(&self) -> &Tag;
     fn name(&self) -> &str;
     fn image_path(&self) -> &str;
+    fn availability(&self) -> Option<&Availability>;
+    fn enabled(&self) -> bool;
+
+    // Methods without a schedule are always available
+    fn is_available_at(&self, moment: DateTime<Utc>) -> bool {
+        self.availability()
+            .map(|a| a.is_open_at(moment))
+            .unwrap_or(true)
+    }
 }
@@ -0,0 +1,341 @@
+use chrono::{
+    DateTime, Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+// Upper bound on how far ahead we look for the next opening moment
+const MAX_LOOKAHEAD_DAYS: u64 = 366;
+// Notation for the end of a day in time ranges
+const END_OF_DAY: &str = "24:00";
+
+fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+/// Time window within a single day, configured as "HH:MM-HH:MM". The end
+/// may be "24:00" for a window lasting until midnight.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeRange {
+    start: NaiveTime,
+    /// `None` for the end of the day
+    end: Option<NaiveTime>,
+}
+
+impl TryFrom<String> for TimeRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let (start, end) = value
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid time range {value}, expected HH:MM-HH:MM"))?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M")
+            .map_err(|e| format!("Invalid start time in {value}: {e}"))?;
+        let end = match end.trim() {
+            END_OF_DAY => None,
+            end => Some(
+                NaiveTime::parse_from_str(end, "%H:%M")
+                    .map_err(|e| format!("Invalid end time in {value}: {e}"))?,
+            ),
+        };
+        if end.is_some_and(|end| start >= end) {
+            return Err(format!("Time range {value} ends before it starts"));
+        }
+
+        Ok(TimeRange { start, end })
+    }
+}
+
+impl From<TimeRange> for String {
+    fn from(value: TimeRange) -> Self {
+        format!(
+            "{}-{}",
+            value.start.format("%H:%M"),
+            value
+                .end
+                .map_or(END_OF_DAY.to_owned(), |end| end.format("%H:%M").to_string())
+        )
+    }
+}
+
+impl TimeRange {
+    fn contains(&self, time: NaiveTime) -> bool {
+        self.start <= time && self.end.map_or(true, |end| time < end)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct OpeningHours {
+    #[serde(default)]
+    monday: Vec<TimeRange>,
+    #[serde(default)]
+    tuesday: Vec<TimeRange>,
+    #[serde(default)]
+    wednesday: Vec<TimeRange>,
+    #[serde(default)]
+    thursday: Vec<TimeRange>,
+    #[serde(default)]
+    friday: Vec<TimeRange>,
+    #[serde(default)]
+    saturday: Vec<TimeRange>,
+    #[serde(default)]
+    sunday: Vec<TimeRange>,
+}
+
+impl OpeningHours {
+    fn for_weekday(&self, weekday: Weekday) -> &[TimeRange] {
+        match weekday {
+            Weekday::Mon => &self.monday,
+            Weekday::Tue => &self.tuesday,
+            Weekday::Wed => &self.wednesday,
+            Weekday::Thu => &self.thursday,
+            Weekday::Fri => &self.friday,
+            Weekday::Sat => &self.saturday,
+            Weekday::Sun => &self.sunday,
+        }
+    }
+}
+
+/// Period during which a method is closed regardless of its opening hours,
+/// expressed in the local time of the schedule.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Closure {
+    from: NaiveDateTime,
+    until: NaiveDateTime,
+}
+
+impl Closure {
+    fn contains(&self, moment: NaiveDateTime) -> bool {
+        self.from <= moment && moment < self.until
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Availability {
+    #[serde(default = "default_timezone")]
+    timezone: Tz,
+    /// Always open, apart from holidays and closures, when absent
+    #[serde(default)]
+    opening_hours: Option<OpeningHours>,
+    #[serde(default)]
+    holidays: Vec<NaiveDate>,
+    #[serde(default)]
+    closures: Vec<Closure>,
+    #[serde(default = "bool::default")]
+    hide_when_closed: bool,
+}
+
+impl Availability {
+    pub fn hide_when_closed(&self) -> bool {
+        self.hide_when_closed
+    }
+
+    pub fn is_open_at(&self, moment: DateTime<Utc>) -> bool {
+        self.is_open_local(moment.with_timezone(&self.timezone).naive_local())
+    }
+
+    fn is_open_local(&self, moment: NaiveDateTime) -> bool {
+        if self.holidays.contains(&moment.date()) {
+            return false;
+        }
+        if self.closures.iter().any(|c| c.contains(moment)) {
+            return false;
+        }
+
+        self.opening_hours.as_ref().map_or(true, |hours| {
+            hours
+                .for_weekday(moment.weekday())
+                .iter()
+                .any(|r| r.contains(moment.time()))
+        })
+    }
+
+    // Times at which the method opens on a day, before holidays and closures
+    fn opening_times(&self, weekday: Weekday) -> Vec<NaiveTime> {
+        match &self.opening_hours {
+            Some(hours) => hours.for_weekday(weekday).iter().map(|r| r.start).collect(),
+            None => vec![NaiveTime::MIN],
+        }
+    }
+
+    /// First moment after `moment` at which the method opens, if it is
+    /// currently closed and opens within the lookahead window.
+    pub fn next_opening(&self, moment: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = moment.with_timezone(&self.timezone).naive_local();
+        if self.is_open_local(local) {
+            return None;
+        }
+
+        (0..=MAX_LOOKAHEAD_DAYS)
+            .filter_map(|offset| local.date().checked_add_days(Days::new(offset)))
+            .filter(|date| !self.holidays.contains(date))
+            .find_map(|date| {
+                // A method opens either at the start of a range or at the end
+                // of a closure that falls within a range
+                let mut candidates: Vec<NaiveDateTime> = self
+                    .opening_times(date.weekday())
+                    .into_iter()
+                    .map(|time| date.and_time(time))
+                    .chain(
+                        self.closures
+                            .iter()
+                            .filter(|c| c.until.date() == date)
+                            .map(|c| c.until),
+                    )
+                    .filter(|candidate| *candidate > local && self.is_open_local(*candidate))
+                    .collect();
+                candidates.sort();
+
+                candidates.into_iter().find_map(|candidate| {
+                    self.timezone
+                        .from_local_datetime(&candidate)
+                        .earliest()
+                        .map(|c| c.with_timezone(&Utc))
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+
+    use super::Availability;
+
+    const TEST_SCHEDULE: &str = r#"
+timezone = "Europe/Amsterdam"
+holidays = ["2024-12-25"]
+
+[opening_hours]
+monday = ["09:00-12:00", "13:00-17:00"]
+tuesday = ["09:00-17:00"]
+wednesday = ["09:00-17:00"]
+thursday = ["09:00-17:00"]
+friday = ["09:00-17:00"]
+
+[[closures]]
+from = "2024-12-27T00:00:00"
+until = "2024-12-27T14:00:00"
+"#;
+
+    fn utc(moment: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(moment).unwrap().into()
+    }
+
+    fn schedule() -> Availability {
+        Figment::from(Toml::string(TEST_SCHEDULE))
+            .extract()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_is_open() {
+        let schedule = schedule();
+
+        // Monday 2024-12-23, 10:00 in Amsterdam
+        assert!(schedule.is_open_at(utc("2024-12-23T09:00:00Z")));
+        // Lunch break
+        assert!(!schedule.is_open_at(utc("2024-12-23T11:30:00Z")));
+        // After hours
+        assert!(!schedule.is_open_at(utc("2024-12-23T16:00:00Z")));
+        // Holiday on a wednesday
+        assert!(!schedule.is_open_at(utc("2024-12-25T09:00:00Z")));
+        // Closed the morning of the 27th
+        assert!(!schedule.is_open_at(utc("2024-12-27T09:00:00Z")));
+        assert!(schedule.is_open_at(utc("2024-12-27T13:30:00Z")));
+        // Weekend
+        assert!(!schedule.is_open_at(utc("2024-12-28T09:00:00Z")));
+    }
+
+    #[test]
+    fn test_next_opening() {
+        let schedule = schedule();
+
+        assert_eq!(schedule.next_opening(utc("2024-12-23T09:00:00Z")), None);
+        assert_eq!(
+            schedule.next_opening(utc("2024-12-23T11:30:00Z")),
+            Some(utc("2024-12-23T12:00:00Z"))
+        );
+        // Skips the holiday
+        assert_eq!(
+            schedule.next_opening(utc("2024-12-24T17:00:00Z")),
+            Some(utc("2024-12-26T08:00:00Z"))
+        );
+        // Reopens when the closure ends
+        assert_eq!(
+            schedule.next_opening(utc("2024-12-26T17:00:00Z")),
+            Some(utc("2024-12-27T13:00:00Z"))
+        );
+        // Over the weekend
+        assert_eq!(
+            schedule.next_opening(utc("2024-12-27T17:00:00Z")),
+            Some(utc("2024-12-30T08:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_without_opening_hours() {
+        let schedule = Figment::from(Toml::string(
+            r#"
+timezone = "Europe/Amsterdam"
+holidays = ["2024-12-25"]
+"#,
+        ))
+        .extract::<Availability>()
+        .unwrap();
+
+        // Open all day, apart from holidays
+        assert!(schedule.is_open_at(utc("2024-12-24T22:30:00Z")));
+        assert!(schedule.is_open_at(utc("2024-12-28T03:00:00Z")));
+        assert!(!schedule.is_open_at(utc("2024-12-25T12:00:00Z")));
+        assert_eq!(
+            schedule.next_opening(utc("2024-12-25T12:00:00Z")),
+            Some(utc("2024-12-25T23:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_until_end_of_day() {
+        let schedule = Figment::from(Toml::string(
+            r#"
+[opening_hours]
+monday = ["18:00-24:00"]
+tuesday = ["00:00-24:00"]
+"#,
+        ))
+        .extract::<Availability>()
+        .unwrap();
+
+        // Monday 2024-12-23 until the end of tuesday
+        assert!(!schedule.is_open_at(utc("2024-12-23T17:59:00Z")));
+        assert!(schedule.is_open_at(utc("2024-12-23T23:59:59Z")));
+        assert!(schedule.is_open_at(utc("2024-12-24T23:59:59Z")));
+        assert!(!schedule.is_open_at(utc("2024-12-25T00:00:00Z")));
+
+        let result = Figment::from(Toml::string(
+            r#"
+[opening_hours]
+monday = ["24:00-24:00"]
+"#,
+        ))
+        .extract::<Availability>();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_invalid_time_range() {
+        let result = Figment::from(Toml::string(
+            r#"
+[opening_hours]
+monday = ["17:00-09:00"]
+"#,
+        ))
+        .extract::<Availability>();
+        assert!(result.is_err());
+    }
+}
@@ -1,15 +1,19 @@
-use std::time::Duration;
+use josekit::jwe::JweEncrypter;
+use serde::{Deserialize, Serialize};
+use verder_helpen_jwt::EncryptionKeyConfig;
+use verder_helpen_proto::{Capability, Correlation, StartCommRequest, StartCommResponse};
 
-use serde::Deserialize;
-use verder_helpen_proto::{StartCommRequest, StartCommResponse};
-
-use super::{Method, Tag};
+use super::{http_client, post_to_plugin, Availability, Method, PluginAuth, Tag};
+use crate::{
+    catalog::default_enabled, config::CoreConfig, db::CoreDbConn, error::Error,
+    form_post::form_post_url, stash::Stash,
+};
 
 fn default_as_false() -> bool {
     false
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CommunicationMethod {
     tag: Tag,
     name: String,
@@ -17,6 +21,30 @@ pub struct CommunicationMethod {
     start: String,
     #[serde(default = "default_as_false")]
     disable_attributes_at_start: bool,
+    #[serde(default)]
+    availability: Option<Availability>,
+    /// Disabled methods are not offered to users
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    /// Key of this plugin, to which core re-encrypts authentication results
+    /// instead of passing on results for the key shared by all plugins
+    #[serde(default)]
+    encryption_pubkey: Option<EncryptionKeyConfig>,
+    /// Canonical attributes the plugin may receive, all when absent. Only
+    /// enforced when results are re-encrypted.
+    #[serde(default)]
+    allowed_attributes: Option<Vec<String>>,
+    /// Credentials for calls to the plugin
+    #[serde(default, skip_serializing)]
+    plugin_auth: Option<PluginAuth>,
+    /// Ways besides the `result` query parameter in which the plugin can
+    /// receive authentication results along with the user
+    #[serde(default)]
+    capabilities: Vec<Capability>,
+    /// Origins users may be sent to by this plugin, further restricting the
+    /// global `allowed_redirect_origins`
+    #[serde(default)]
+    allowed_redirect_origins: Option<Vec<String>>,
 }
 
 impl Method for CommunicationMethod {
@@ -31,25 +59,78 @@ impl Method for CommunicationMethod {
     fn image_path(&self) -> &str {
         &self.image_path
     }
+
+    fn availability(&self) -> Option<&Availability> {
+        self.availability.as_ref()
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
 }
 
 impl CommunicationMethod {
+    /// Whether authentication results for this plugin pass through core to be
+    /// re-encrypted
+    pub fn reencrypts(&self) -> bool {
+        self.encryption_pubkey.is_some()
+    }
+
+    pub fn encrypter(&self) -> Result<Option<Box<dyn JweEncrypter>>, verder_helpen_jwt::Error> {
+        self.encryption_pubkey
+            .clone()
+            .map(Box::<dyn JweEncrypter>::try_from)
+            .transpose()
+    }
+
+    pub fn allowed_attributes(&self) -> Option<&[String]> {
+        self.allowed_attributes.as_deref()
+    }
+
+    pub fn capabilities(&self) -> &[Capability] {
+        &self.capabilities
+    }
+
+    pub fn allowed_redirect_origins(&self) -> Option<&[String]> {
+        self.allowed_redirect_origins.as_deref()
+    }
+
+    pub fn plugin_auth(&self) -> Option<&PluginAuth> {
+        self.plugin_auth.as_ref()
+    }
+
+    /// Take the credentials of the configured method `base`, as credentials
+    /// can not be set through overrides
+    pub fn with_credentials_of(self, base: Option<&Self>) -> Self {
+        Self {
+            plugin_auth: base.and_then(|b| b.plugin_auth.clone()),
+            ..self
+        }
+    }
+
+    /// Whether results are posted to the plugin rather than added to its url
+    pub fn form_post(&self) -> bool {
+        self.capabilities.contains(&Capability::FormPost)
+    }
+
     // Start a communication session to be composed with an authentication session
-    pub async fn start(&self, purpose: &str) -> Result<StartCommResponse, reqwest::Error> {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(5))
-            .build()?;
-
-        client
-            .post(&format!("{}/start_communication", &self.start))
-            .json(&StartCommRequest {
+    pub async fn start(
+        &self,
+        purpose: &str,
+        correlation: &Correlation,
+    ) -> Result<StartCommResponse, Error> {
+        Ok(post_to_plugin(
+            &format!("{}/start_communication", &self.start),
+            &StartCommRequest {
                 purpose: purpose.to_string(),
                 auth_result: None,
-            })
-            .send()
-            .await?
-            .json::<StartCommResponse>()
-            .await
+            },
+            self.plugin_auth.as_ref(),
+            correlation,
+        )
+        .await?
+        .json::<StartCommResponse>()
+        .await?)
     }
 
     // Falback for plugins not supporting attribute reception on startup
@@ -57,15 +138,18 @@ impl CommunicationMethod {
         &self,
         purpose: &str,
         auth_result: &str,
-    ) -> Result<StartCommResponse, reqwest::Error> {
-        let comm_data = self.start(purpose).await?;
+        config: &CoreConfig,
+        stash: &Stash,
+        db: Option<&CoreDbConn>,
+        correlation: &Correlation,
+    ) -> Result<StartCommResponse, Error> {
+        let comm_data = self.start(purpose, correlation).await?;
+        // The result is handed over below, so the user must end up somewhere
+        // allowed before anything is sent
+        config.check_redirect(&comm_data.client_url, Some(self))?;
 
         if let Some(attr_url) = comm_data.attr_url {
-            let client = reqwest::Client::builder()
-                .timeout(Duration::from_secs(5))
-                .build()?;
-
-            client
+            http_client(correlation)?
                 .post(&attr_url)
                 .header("Content-Type", "application/jwt")
                 .body(auth_result.to_string())
@@ -76,6 +160,12 @@ impl CommunicationMethod {
                 client_url: comm_data.client_url,
                 attr_url: None,
             })
+        } else if self.form_post() {
+            Ok(StartCommResponse {
+                client_url: form_post_url(config, &comm_data.client_url, auth_result, stash, db)
+                    .await?,
+                attr_url: None,
+            })
         } else {
             Ok(StartCommResponse {
                 client_url: if comm_data.client_url.contains('?') {
@@ -94,35 +184,75 @@ impl CommunicationMethod {
         &self,
         purpose: &str,
         auth_result: &str,
-    ) -> Result<StartCommResponse, reqwest::Error> {
+        config: &CoreConfig,
+        stash: &Stash,
+        db: Option<&CoreDbConn>,
+        correlation: &Correlation,
+    ) -> Result<StartCommResponse, Error> {
         if self.disable_attributes_at_start {
             return self
-                .start_with_attributes_fallback(purpose, auth_result)
+                .start_with_attributes_fallback(
+                    purpose,
+                    auth_result,
+                    config,
+                    stash,
+                    db,
+                    correlation,
+                )
                 .await;
         }
 
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(5))
-            .build()?;
-
-        client
-            .post(&format!("{}/start_communication", &self.start))
-            .json(&StartCommRequest {
+        let comm_data = post_to_plugin(
+            &format!("{}/start_communication", &self.start),
+            &StartCommRequest {
                 purpose: purpose.to_string(),
                 auth_result: Some(auth_result.to_string()),
-            })
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<StartCommResponse>()
-            .await
+            },
+            self.plugin_auth.as_ref(),
+            correlation,
+        )
+        .await?
+        .error_for_status()?
+        .json::<StartCommResponse>()
+        .await?;
+        config.check_redirect(&comm_data.client_url, Some(self))?;
+        Ok(comm_data)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use figment::providers::{Format, Toml};
     use httpmock::MockServer;
+    use rocket::{figment::Figment, http::Status, local::blocking::Client};
     use serde_json::json;
+    use verder_helpen_proto::{Capability, Correlation};
+
+    use crate::{
+        config::CoreConfig, error::Error, methods::PluginAuth, setup_routes, stash::Stash,
+    };
+
+    const TEST_CONFIG: &str = r#"
+[global]
+server_url = "https://core.verderhelpen.nl"
+internal_url = "http://core:8000"
+internal_secret = "sample_secret_1234567890178901237890"
+auth_methods = []
+comm_methods = []
+purposes = []
+
+[global.authonly_request_keys]
+"#;
+
+    fn figment() -> Figment {
+        Figment::from(rocket::Config::default())
+            .select(rocket::Config::DEFAULT_PROFILE)
+            .merge(Toml::string(TEST_CONFIG).nested())
+    }
+
+    fn config() -> CoreConfig {
+        figment().extract::<CoreConfig>().unwrap()
+    }
 
     #[test]
     fn test_start_without_attributes_no_attrurl() {
@@ -146,9 +276,16 @@ mod tests {
             image_path: "none".into(),
             start: server.base_url(),
             disable_attributes_at_start: false,
+            availability: None,
+            enabled: true,
+            encryption_pubkey: None,
+            allowed_attributes: None,
+            plugin_auth: None,
+            capabilities: vec![],
+            allowed_redirect_origins: None,
         };
 
-        let result = tokio_test::block_on(method.start("something"));
+        let result = tokio_test::block_on(method.start("something", &Correlation::generate()));
 
         start_mock.assert();
         let result = result.unwrap();
@@ -156,6 +293,46 @@ mod tests {
         assert_eq!(result.attr_url, None);
     }
 
+    #[test]
+    fn test_start_with_plugin_auth() {
+        let server = MockServer::start();
+        let start_mock = server.mock(|when, then| {
+            when.path("/start_communication")
+                .method(httpmock::Method::POST)
+                .header("Authorization", "Bearer sample_token")
+                .json_body(json!({
+                    "purpose": "something"
+                }));
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({
+                    "client_url": "https://example.com/client_url",
+                }));
+        });
+
+        let method = super::CommunicationMethod {
+            tag: "test".into(),
+            name: "test".into(),
+            image_path: "none".into(),
+            start: server.base_url(),
+            disable_attributes_at_start: false,
+            availability: None,
+            enabled: true,
+            encryption_pubkey: None,
+            allowed_attributes: None,
+            plugin_auth: Some(PluginAuth::Bearer {
+                token: "sample_token".to_string().into(),
+            }),
+            capabilities: vec![],
+            allowed_redirect_origins: None,
+        };
+
+        let result = tokio_test::block_on(method.start("something", &Correlation::generate()));
+
+        start_mock.assert();
+        assert_eq!(result.unwrap().client_url, "https://example.com/client_url");
+    }
+
     #[test]
     fn test_start_without_attributes_attrurl() {
         let server = MockServer::start();
@@ -179,9 +356,16 @@ mod tests {
             image_path: "none".into(),
             start: server.base_url(),
             disable_attributes_at_start: false,
+            availability: None,
+            enabled: true,
+            encryption_pubkey: None,
+            allowed_attributes: None,
+            plugin_auth: None,
+            capabilities: vec![],
+            allowed_redirect_origins: None,
         };
 
-        let result = tokio_test::block_on(method.start("something"));
+        let result = tokio_test::block_on(method.start("something", &Correlation::generate()));
 
         start_mock.assert();
         let result = result.unwrap();
@@ -212,9 +396,23 @@ mod tests {
             image_path: "none".into(),
             start: server.base_url(),
             disable_attributes_at_start: false,
+            availability: None,
+            enabled: true,
+            encryption_pubkey: None,
+            allowed_attributes: None,
+            plugin_auth: None,
+            capabilities: vec![],
+            allowed_redirect_origins: None,
         };
 
-        let result = tokio_test::block_on(method.start_with_auth_result("something", "test"));
+        let result = tokio_test::block_on(method.start_with_auth_result(
+            "something",
+            "test",
+            &config(),
+            &Stash::default(),
+            None,
+            &Correlation::generate(),
+        ));
 
         start_mock.assert();
         let result = result.unwrap();
@@ -252,9 +450,23 @@ mod tests {
             image_path: "none".into(),
             start: server.base_url(),
             disable_attributes_at_start: true,
+            availability: None,
+            enabled: true,
+            encryption_pubkey: None,
+            allowed_attributes: None,
+            plugin_auth: None,
+            capabilities: vec![],
+            allowed_redirect_origins: None,
         };
 
-        let result = tokio_test::block_on(method.start_with_auth_result("something", "test"));
+        let result = tokio_test::block_on(method.start_with_auth_result(
+            "something",
+            "test",
+            &config(),
+            &Stash::default(),
+            None,
+            &Correlation::generate(),
+        ));
 
         start_mock.assert();
         auth_mock.assert();
@@ -263,6 +475,54 @@ mod tests {
         assert_eq!(result.attr_url, None);
     }
 
+    #[test]
+    fn test_auth_result_fallback_disallowed_redirect() {
+        let server = MockServer::start();
+        let start_mock = server.mock(|when, then| {
+            when.path("/start_communication")
+                .method(httpmock::Method::POST);
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({
+                    "client_url": "https://evil.example.com/client_url",
+                    "attr_url": format!("{}/attr_url", server.base_url()),
+                }));
+        });
+        let auth_mock = server.mock(|when, then| {
+            when.path("/attr_url");
+            then.status(200);
+        });
+
+        let method = super::CommunicationMethod {
+            tag: "test".into(),
+            name: "test".into(),
+            image_path: "none".into(),
+            start: server.base_url(),
+            disable_attributes_at_start: true,
+            availability: None,
+            enabled: true,
+            encryption_pubkey: None,
+            allowed_attributes: None,
+            plugin_auth: None,
+            capabilities: vec![],
+            allowed_redirect_origins: Some(vec!["https://example.com".into()]),
+        };
+
+        let result = tokio_test::block_on(method.start_with_auth_result(
+            "something",
+            "test",
+            &config(),
+            &Stash::default(),
+            None,
+            &Correlation::generate(),
+        ));
+
+        // The result is not delivered for a session the user can not be sent to
+        start_mock.assert();
+        auth_mock.assert_hits(0);
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+    }
+
     #[test]
     fn test_auth_result_fallback_no_attr_url() {
         let server = MockServer::start();
@@ -285,9 +545,23 @@ mod tests {
             image_path: "none".into(),
             start: server.base_url(),
             disable_attributes_at_start: true,
+            availability: None,
+            enabled: true,
+            encryption_pubkey: None,
+            allowed_attributes: None,
+            plugin_auth: None,
+            capabilities: vec![],
+            allowed_redirect_origins: None,
         };
 
-        let result = tokio_test::block_on(method.start_with_auth_result("something", "test"));
+        let result = tokio_test::block_on(method.start_with_auth_result(
+            "something",
+            "test",
+            &config(),
+            &Stash::default(),
+            None,
+            &Correlation::generate(),
+        ));
 
         start_mock.assert();
         let result = result.unwrap();
@@ -297,4 +571,70 @@ mod tests {
         );
         assert_eq!(result.attr_url, None);
     }
+
+    #[test]
+    fn test_auth_result_fallback_form_post() {
+        let server = MockServer::start();
+        let start_mock = server.mock(|when, then| {
+            when.path("/start_communication")
+                .method(httpmock::Method::POST)
+                .json_body(json!({
+                    "purpose": "something",
+                }));
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({
+                    "client_url": "https://example.com/client_url",
+                }));
+        });
+
+        let method = super::CommunicationMethod {
+            tag: "test".into(),
+            name: "test".into(),
+            image_path: "none".into(),
+            start: server.base_url(),
+            disable_attributes_at_start: true,
+            availability: None,
+            enabled: true,
+            encryption_pubkey: None,
+            allowed_attributes: None,
+            plugin_auth: None,
+            capabilities: vec![Capability::FormPost],
+            allowed_redirect_origins: None,
+        };
+
+        // The page is served by a core sharing the stash
+        let client = Client::tracked(setup_routes(rocket::custom(figment()))).unwrap();
+        let result = tokio_test::block_on(method.start_with_auth_result(
+            "something",
+            "test",
+            &config(),
+            client.rocket().state::<Stash>().unwrap(),
+            None,
+            &Correlation::generate(),
+        ));
+
+        start_mock.assert();
+        let result = result.unwrap();
+        assert!(!result.client_url.contains("result="));
+        let path = result
+            .client_url
+            .strip_prefix("https://core.verderhelpen.nl")
+            .unwrap();
+        assert!(path.starts_with("/form_post/"));
+
+        let response = client.get(path).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Cache-Control"),
+            Some("no-store")
+        );
+        let page = response.into_string().unwrap();
+        assert!(page.contains(r#"<form method="post" action="https://example.com/client_url">"#));
+        assert!(page.contains(r#"name="result" value="test""#));
+
+        // The result is handed over only once
+        let response = client.get(path).dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
 }
@@ -0,0 +1,89 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::{Certificate, ClientBuilder, Identity, RequestBuilder};
+use serde::Deserialize;
+use verder_helpen_proto::{body_signature, SIGNATURE_HEADER};
+
+use crate::{config::TokenSecret, error::Error};
+
+/// Credentials with which core authenticates its calls to the start endpoint
+/// of a plugin. These are only read from the configuration file, never
+/// serialized, so they can not leak through the admin API or the overrides
+/// file.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PluginAuth {
+    /// Token sent in the `Authorization` header
+    Bearer { token: TokenSecret },
+    /// Secret with which the request body is signed, in the same way as
+    /// webhook payloads. Must be at least `TokenSecret::MIN_HMAC_LEN` bytes.
+    Hmac { secret: TokenSecret },
+    /// PEM encoded client certificate and PKCS#8 private key
+    Mtls {
+        certificate: String,
+        key: TokenSecret,
+        /// CA certificate of the plugin, when not publicly trusted
+        #[serde(default)]
+        ca_certificate: Option<String>,
+    },
+}
+
+impl PluginAuth {
+    /// Bodies are signed with HMAC-SHA256, which needs a long enough key
+    pub fn check_secret(&self, plugin: &str) -> Result<(), Error> {
+        match self {
+            PluginAuth::Hmac { secret } if secret.as_bytes().len() < TokenSecret::MIN_HMAC_LEN => {
+                Err(Error::InvalidConfig(format!(
+                    "Secret of plugin {plugin} must be at least {} bytes",
+                    TokenSecret::MIN_HMAC_LEN
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Set up the client certificate, if any
+    pub fn configure(&self, builder: ClientBuilder) -> Result<ClientBuilder, reqwest::Error> {
+        let PluginAuth::Mtls {
+            certificate,
+            key,
+            ca_certificate,
+        } = self
+        else {
+            return Ok(builder);
+        };
+
+        let builder = builder.identity(Identity::from_pkcs8_pem(
+            certificate.as_bytes(),
+            key.as_bytes(),
+        )?);
+        match ca_certificate {
+            Some(ca_certificate) => {
+                Ok(builder.add_root_certificate(Certificate::from_pem(ca_certificate.as_bytes())?))
+            }
+            None => Ok(builder),
+        }
+    }
+
+    /// Add the credentials for sending the given body
+    pub fn authenticate(
+        &self,
+        request: RequestBuilder,
+        body: &str,
+    ) -> Result<RequestBuilder, Error> {
+        match self {
+            PluginAuth::Bearer { token } => Ok(request.bearer_auth(token.as_str())),
+            PluginAuth::Hmac { secret } => {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                Ok(request.header(
+                    SIGNATURE_HEADER,
+                    body_signature(secret.as_bytes(), timestamp, body)?,
+                ))
+            }
+            PluginAuth::Mtls { .. } => Ok(request),
+        }
+    }
+}
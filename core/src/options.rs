@@ -1,9 +1,11 @@
 use std::collections::HashMap;
 
-use rocket::{serde::json::Json, State};
+use chrono::{DateTime, Utc};
+use rocket::serde::json::Json;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    catalog::{Catalog, LocalizedText, MethodDisplay, Purpose},
     config::CoreConfig,
     error::Error,
     methods::{Method, Tag},
@@ -14,55 +16,104 @@ struct MethodProperties {
     tag: Tag,
     name: String,
     image_path: String,
+    available: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_opening: Option<DateTime<Utc>>,
+    #[serde(default)]
+    order: i32,
+    #[serde(default)]
+    recommended: bool,
 }
 
 impl MethodProperties {
     fn filter_methods_by_tags<'a, T: Method, I: Iterator<Item = &'a String>>(
         tags: I,
         methods: &HashMap<String, T>,
+        display: &HashMap<Tag, MethodDisplay>,
+        now: DateTime<Utc>,
     ) -> Result<Vec<MethodProperties>, Error> {
-        tags.map(|t| {
+        let mut result = vec![];
+        for t in tags {
             let method = methods
                 .get(t)
                 .ok_or_else(|| Error::NoSuchMethod(t.clone()))?;
-            Ok(MethodProperties {
+            let available = method.is_available_at(now);
+            let availability = method.availability();
+
+            if !available && availability.map(|a| a.hide_when_closed()).unwrap_or(false) {
+                continue;
+            }
+
+            let display = display.get(t).cloned().unwrap_or_default();
+            result.push(MethodProperties {
                 tag: String::from(method.tag()),
                 name: String::from(method.name()),
                 image_path: String::from(method.image_path()),
-            })
-        })
-        .collect()
+                available,
+                next_opening: availability.and_then(|a| a.next_opening(now)),
+                order: display.order,
+                recommended: display.recommended,
+            });
+        }
+
+        result.sort_by(|a, b| a.order.cmp(&b.order).then_with(|| a.tag.cmp(&b.tag)));
+        Ok(result)
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SessionOptions {
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) title: LocalizedText,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) description: LocalizedText,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) privacy_notice: LocalizedText,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
     auth_methods: Vec<MethodProperties>,
     comm_methods: Vec<MethodProperties>,
 }
 
+impl SessionOptions {
+    pub(crate) fn for_purpose(
+        purpose: &Purpose,
+        catalog: &Catalog,
+        now: DateTime<Utc>,
+    ) -> Result<SessionOptions, Error> {
+        Ok(SessionOptions {
+            title: purpose.title.clone(),
+            description: purpose.description.clone(),
+            privacy_notice: purpose.privacy_notice.clone(),
+            icon: purpose.icon.clone(),
+            auth_methods: MethodProperties::filter_methods_by_tags(
+                purpose.allowed_auth.iter(),
+                &catalog.auth_methods,
+                &purpose.auth_display,
+                now,
+            )?,
+            comm_methods: MethodProperties::filter_methods_by_tags(
+                purpose.allowed_comm.iter(),
+                &catalog.comm_methods,
+                &purpose.comm_display,
+                now,
+            )?,
+        })
+    }
+}
+
 type AllSessionOptions = HashMap<String, SessionOptions>;
 
 #[get("/session_options")]
-pub fn all_session_options(config: &State<CoreConfig>) -> Result<Json<AllSessionOptions>, Error> {
+pub fn all_session_options(config: &CoreConfig) -> Result<Json<AllSessionOptions>, Error> {
     let mut all_options: AllSessionOptions = HashMap::new();
+    let now = Utc::now();
+    let catalog = config.catalog();
 
-    for (name, purpose) in &config.purposes {
-        let auth_methods = MethodProperties::filter_methods_by_tags(
-            purpose.allowed_auth.iter(),
-            &config.auth_methods,
-        )?;
-        let comm_methods = MethodProperties::filter_methods_by_tags(
-            purpose.allowed_comm.iter(),
-            &config.comm_methods,
-        )?;
-
+    for (name, purpose) in &catalog.purposes {
         all_options.insert(
             name.to_string(),
-            SessionOptions {
-                auth_methods,
-                comm_methods,
-            },
+            SessionOptions::for_purpose(purpose, &catalog, now)?,
         );
     }
 
@@ -70,27 +121,15 @@ pub fn all_session_options(config: &State<CoreConfig>) -> Result<Json<AllSession
 }
 
 #[get("/session_options/<purpose>")]
-pub fn session_options(
-    purpose: &str,
-    config: &State<CoreConfig>,
-) -> Result<Json<SessionOptions>, Error> {
-    let purpose = config
-        .purposes
-        .get(purpose)
-        .ok_or_else(|| Error::NoSuchPurpose(purpose.to_owned()))?;
-    let auth_methods = MethodProperties::filter_methods_by_tags(
-        purpose.allowed_auth.iter(),
-        &config.auth_methods,
-    )?;
-    let comm_methods = MethodProperties::filter_methods_by_tags(
-        purpose.allowed_comm.iter(),
-        &config.comm_methods,
-    )?;
-
-    Ok(Json(SessionOptions {
-        auth_methods,
-        comm_methods,
-    }))
+pub fn session_options(purpose: &str, config: &CoreConfig) -> Result<Json<SessionOptions>, Error> {
+    let catalog = config.catalog();
+    let purpose = catalog.purpose(purpose)?;
+
+    Ok(Json(SessionOptions::for_purpose(
+        purpose,
+        &catalog,
+        Utc::now(),
+    )?))
 }
 
 #[cfg(test)]
@@ -99,7 +138,7 @@ mod tests {
     use rocket::{figment::Figment, http::Status, local::blocking::Client};
 
     use super::SessionOptions;
-    use crate::setup_routes;
+    use crate::{config::CoreConfig, setup_routes};
 
     const TEST_CONFIG_VALID: &str = r#"
 [global]
@@ -230,4 +269,107 @@ allowed_comm = [ "call" ]
         let response = client.get("/session_options/does_not_exist").dispatch();
         assert_ne!(response.status(), Status::Ok);
     }
+
+    #[test]
+    fn test_options_availability() {
+        // Methods with empty opening hours are never open
+        let config = format!(
+            r#"{TEST_CONFIG_VALID}
+[[global.comm_methods]]
+tag = "video"
+name = "Videobellen"
+image_path = "/static/video.svg"
+start = "http://comm-video:8000"
+
+[global.comm_methods.availability]
+timezone = "Europe/Amsterdam"
+opening_hours = {{}}
+
+[[global.comm_methods]]
+tag = "visit"
+name = "Langskomen"
+image_path = "/static/visit.svg"
+start = "http://comm-visit:8000"
+
+[global.comm_methods.availability]
+hide_when_closed = true
+opening_hours = {{}}
+"#
+        );
+        let figment = Figment::from(rocket::Config::default())
+            .select(rocket::Config::DEFAULT_PROFILE)
+            .merge(Toml::string(&config).nested());
+
+        let client = Client::tracked(setup_routes(rocket::custom(figment))).unwrap();
+
+        let response = client.get("/session_options/request_permit").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response =
+            serde_json::from_slice::<SessionOptions>(&response.into_bytes().unwrap()).unwrap();
+        assert_eq!(response.comm_methods.len(), 3);
+        assert!(response
+            .comm_methods
+            .iter()
+            .any(|m| m.tag == "call" && m.available));
+        let video = response
+            .comm_methods
+            .iter()
+            .find(|m| m.tag == "video")
+            .unwrap();
+        assert!(!video.available);
+        assert!(video.next_opening.is_none());
+        assert!(!response.comm_methods.iter().any(|m| m.tag == "visit"));
+    }
+
+    #[test]
+    fn test_options_metadata() {
+        let config = TEST_CONFIG_VALID.replace(
+            "allowed_comm = [ \"call\" ]\n",
+            r#"allowed_comm = [ "call" ]
+icon = "/static/passport.svg"
+title = { nl = "Paspoort aanvragen", en = "Request a passport" }
+privacy_notice = { nl = "We vragen je e-mailadres om je te kunnen bereiken." }
+auth_display = { irma = { order = 1, recommended = true } }
+"#,
+        );
+        let figment = Figment::from(rocket::Config::default())
+            .select(rocket::Config::DEFAULT_PROFILE)
+            .merge(Toml::string(&config).nested());
+        let client = Client::tracked(setup_routes(rocket::custom(figment))).unwrap();
+
+        let response = client.get("/session_options/request_passport").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response =
+            serde_json::from_slice::<SessionOptions>(&response.into_bytes().unwrap()).unwrap();
+        assert_eq!(response.title["en"], "Request a passport");
+        assert!(response.privacy_notice.contains_key("nl"));
+        assert!(response.description.is_empty());
+        assert_eq!(response.icon.as_deref(), Some("/static/passport.svg"));
+        assert!(response.auth_methods[0].recommended);
+        assert!(!response.comm_methods[0].recommended);
+
+        // Methods are ordered by their display order, then by tag
+        let response = client.get("/session_options/report_move").dispatch();
+        let response =
+            serde_json::from_slice::<SessionOptions>(&response.into_bytes().unwrap()).unwrap();
+        let tags: Vec<&str> = response
+            .auth_methods
+            .iter()
+            .map(|m| m.tag.as_str())
+            .collect();
+        assert_eq!(tags, vec!["digid", "irma"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_display_unlisted_method() {
+        let config = TEST_CONFIG_VALID.replace(
+            "allowed_comm = [ \"call\" ]\n",
+            "allowed_comm = [ \"call\" ]\ncomm_display = { chat = { order = 1 } }\n",
+        );
+        let figment = Figment::from(rocket::Config::default())
+            .select(rocket::Config::DEFAULT_PROFILE)
+            .merge(Toml::string(&config).nested());
+        figment.extract::<CoreConfig>().unwrap();
+    }
 }
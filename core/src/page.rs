@@ -0,0 +1,208 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::OnceLock,
+};
+
+use rocket::{
+    figment::{
+        providers::{Format, Toml},
+        Figment,
+    },
+    http::Status,
+    response::content::RawHtml,
+    Request,
+};
+use serde::Serialize;
+use tera::{Context, Tera};
+
+use crate::methods::Tag;
+
+/// Texts of the pages of one language, by page and key
+type PageTexts = HashMap<String, HashMap<String, String>>;
+
+static TRANSLATIONS: OnceLock<HashMap<String, PageTexts>> = OnceLock::new();
+
+// Texts of all languages, from the translations table
+fn translations() -> &'static HashMap<String, PageTexts> {
+    TRANSLATIONS.get_or_init(|| {
+        Figment::from(Toml::string(include_str!("templates/translations.toml")))
+            .extract()
+            .unwrap_or_else(|e| {
+                log::error!("Could not load translations: {e}");
+                panic!("Could not load translations: {e}")
+            })
+    })
+}
+
+/// Texts of the pages shown to a user, in the language they prefer
+#[derive(Debug, Clone, Copy)]
+pub struct Texts {
+    lang: &'static str,
+    pages: &'static PageTexts,
+}
+
+impl Texts {
+    /// Texts in English when the user prefers that, otherwise in Dutch
+    pub fn for_request(request: &Request<'_>) -> Self {
+        Self::for_lang(if prefers_english(request) { "en" } else { "nl" })
+    }
+
+    fn for_lang(lang: &'static str) -> Self {
+        Texts {
+            lang,
+            pages: &translations()[lang],
+        }
+    }
+
+    pub fn lang(&self) -> &'static str {
+        self.lang
+    }
+
+    /// All texts of a page
+    pub fn page(&self, page: &str) -> &'static HashMap<String, String> {
+        &self.pages[page]
+    }
+
+    /// A single text of a page
+    pub fn get(&self, page: &str, key: &str) -> &'static str {
+        &self.page(page)[key]
+    }
+}
+
+// Whether English should be used for pages shown to the user, rather than
+// Dutch
+fn prefers_english(request: &Request<'_>) -> bool {
+    request
+        .headers()
+        .get_one("Accept-Language")
+        .map(accept_language::parse)
+        .and_then(|languages| {
+            languages
+                .into_iter()
+                .map(|l| {
+                    l.split('-')
+                        .next()
+                        .unwrap_or_default()
+                        .trim()
+                        .to_lowercase()
+                })
+                .find(|l| l == "nl" || l == "en")
+        })
+        .is_some_and(|l| l == "en")
+}
+
+static TEMPLATES: OnceLock<Tera> = OnceLock::new();
+
+// Templates of the pages shown to the user, sharing a single base layout
+fn templates() -> &'static Tera {
+    TEMPLATES.get_or_init(|| {
+        let mut templates = Tera::default();
+        templates
+            .add_raw_templates(vec![
+                ("base.html", include_str!("templates/base.html")),
+                ("message.html", include_str!("templates/message.html")),
+                ("methods.html", include_str!("templates/methods.html")),
+                ("form_post.html", include_str!("templates/form_post.html")),
+            ])
+            .unwrap_or_else(|e| {
+                log::error!("Could not load page templates: {e}");
+                panic!("Could not load page templates: {e}")
+            });
+        // Unlike the default escaping of Tera, urls are kept readable
+        templates.set_escape_fn(escape_html);
+        templates
+    })
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+/// Render a page shown to the user. Values in the context are escaped by the
+/// templates.
+pub fn render_page(
+    template: &str,
+    lang: &str,
+    title: &str,
+    mut context: Context,
+) -> Result<RawHtml<String>, Status> {
+    context.insert("lang", lang);
+    context.insert("title", title);
+    match templates().render(template, &context) {
+        Ok(page) => Ok(RawHtml(page)),
+        Err(e) => {
+            log::error!("Could not render {template}: {e}");
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+/// Page with a title and some paragraphs of text
+pub fn message_page(
+    lang: &str,
+    title: &str,
+    paragraphs: &[&str],
+) -> Result<RawHtml<String>, Status> {
+    let mut context = Context::new();
+    context.insert("paragraphs", paragraphs);
+    render_page("message.html", lang, title, context)
+}
+
+/// Auth method to choose on a page, by tag and name
+#[derive(Debug, Serialize)]
+pub struct MethodChoice<'a> {
+    tag: &'a str,
+    name: &'a str,
+}
+
+/// Page on which the user chooses one of `methods`, each posting the chosen
+/// auth method and the hidden `fields` to `action`
+pub fn methods_page(
+    lang: &str,
+    title: &str,
+    [message, none_left]: [&str; 2],
+    action: &str,
+    fields: &[(&str, &str)],
+    methods: &[(Tag, String)],
+) -> Result<RawHtml<String>, Status> {
+    let methods: Vec<MethodChoice> = methods
+        .iter()
+        .map(|(tag, name)| MethodChoice { tag, name })
+        .collect();
+    let mut context = Context::new();
+    context.insert("message", message);
+    context.insert("none_left", none_left);
+    context.insert("action", action);
+    context.insert(
+        "fields",
+        &fields.iter().copied().collect::<BTreeMap<_, _>>(),
+    );
+    context.insert("methods", &methods);
+    render_page("methods.html", lang, title, context)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::{translations, Texts};
+
+    #[test]
+    fn test_translations_complete() {
+        let keys = |lang: &str| -> BTreeSet<(String, String)> {
+            translations()[lang]
+                .iter()
+                .flat_map(|(page, texts)| texts.keys().map(|key| (page.clone(), key.clone())))
+                .collect()
+        };
+        assert_eq!(keys("nl"), keys("en"));
+        assert_eq!(translations().len(), 2);
+
+        assert_eq!(Texts::for_lang("en").get("form_post", "title"), "Continue");
+        assert_eq!(Texts::for_lang("nl").get("form_post", "title"), "Doorgaan");
+    }
+}
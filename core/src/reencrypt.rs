@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use josekit::{
+    jwe::JweDecrypter,
+    jws::{JwsSigner, JwsVerifier},
+};
+use serde::Deserialize;
+use verder_helpen_jwt::{EncryptionKeyConfig, SignKeyConfig};
+
+use crate::{
+    error::Error,
+    methods::{AuthenticationMethod, CommunicationMethod, Method},
+};
+
+#[derive(Debug, Deserialize)]
+struct RawAuthResultKeys {
+    /// Private part of the `encryption_pubkey` the authentication plugins
+    /// encrypt their results with
+    decryption_privkey: EncryptionKeyConfig,
+    /// Keys with which the authentication plugins sign their results, by
+    /// auth method, needed for leaving out attributes
+    #[serde(default)]
+    signature_pubkeys: HashMap<String, SignKeyConfig>,
+    /// Key with which core signs results it left attributes out of
+    signing_privkey: Option<SignKeyConfig>,
+}
+
+/// Keys with which core re-encrypts authentication results for
+/// communication plugins that have their own key
+#[derive(Debug, Deserialize)]
+#[serde(from = "RawAuthResultKeys")]
+pub struct AuthResultKeys {
+    decrypter: Box<dyn JweDecrypter>,
+    filter_keys: Option<FilterKeys>,
+}
+
+/// Keys for verifying results per auth method, and signing them again after
+/// attributes were left out
+#[derive(Debug)]
+struct FilterKeys {
+    verifiers: HashMap<String, Box<dyn JwsVerifier>>,
+    signer: Box<dyn JwsSigner>,
+}
+
+impl From<RawAuthResultKeys> for AuthResultKeys {
+    fn from(config: RawAuthResultKeys) -> Self {
+        let decrypter = Box::<dyn JweDecrypter>::try_from(config.decryption_privkey)
+            .unwrap_or_else(|_| {
+                log::error!("Could not parse auth result decryption key");
+                panic!("Invalid auth result decryption key")
+            });
+        let filter_keys = match (config.signature_pubkeys, config.signing_privkey) {
+            (verifiers, Some(signer)) if !verifiers.is_empty() => Some(FilterKeys {
+                verifiers: verifiers
+                    .into_iter()
+                    .map(|(auth_method, verifier)| {
+                        let verifier =
+                            Box::<dyn JwsVerifier>::try_from(verifier).unwrap_or_else(|_| {
+                                log::error!(
+                                    "Could not parse auth result signature key for {auth_method}"
+                                );
+                                panic!("Invalid auth result signature key")
+                            });
+                        (auth_method, verifier)
+                    })
+                    .collect(),
+                signer: Box::<dyn JwsSigner>::try_from(signer).unwrap_or_else(|_| {
+                    log::error!("Could not parse auth result signing key");
+                    panic!("Invalid auth result signing key")
+                }),
+            }),
+            (verifiers, None) if verifiers.is_empty() => None,
+            _ => {
+                log::error!("Auth result signature_pubkeys and signing_privkey go together");
+                panic!("Auth result signature_pubkeys and signing_privkey go together")
+            }
+        };
+
+        AuthResultKeys {
+            decrypter,
+            filter_keys,
+        }
+    }
+}
+
+/// Check that results can be re-encrypted for all communication methods with
+/// their own key, and filtered whatever auth method they come from
+pub fn check_reencryption(
+    keys: Option<&AuthResultKeys>,
+    auth_methods: &HashMap<String, AuthenticationMethod>,
+    comm_methods: &HashMap<String, CommunicationMethod>,
+) -> Result<(), String> {
+    for method in comm_methods.values() {
+        if method.encrypter().is_err() {
+            return Err(format!(
+                "Invalid encryption key for comm method {}",
+                method.tag()
+            ));
+        }
+        if !method.reencrypts() {
+            continue;
+        }
+        match keys {
+            None => {
+                return Err(format!(
+                    "Comm method {} has its own key, but auth_result_keys are missing",
+                    method.tag()
+                ))
+            }
+            Some(keys) if method.allowed_attributes().is_some() => {
+                let Some(filter_keys) = &keys.filter_keys else {
+                    return Err(format!(
+                        "Comm method {} restricts attributes, but auth_result_keys can not sign \
+                         results",
+                        method.tag()
+                    ));
+                };
+                if let Some(auth_method) = auth_methods
+                    .keys()
+                    .find(|tag| !filter_keys.verifiers.contains_key(*tag))
+                {
+                    return Err(format!(
+                        "Comm method {} restricts attributes, but auth_result_keys has no \
+                         signature key for auth method {auth_method}",
+                        method.tag()
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+impl AuthResultKeys {
+    /// Encrypt a result from an authentication plugin for the given
+    /// communication method, leaving out the attributes it may not receive.
+    /// Attribute names as mapped for the authentication method are
+    /// recognized as well.
+    pub fn reencrypt(
+        &self,
+        result: &str,
+        comm_method: &CommunicationMethod,
+        auth_method: Option<&AuthenticationMethod>,
+    ) -> Result<String, Error> {
+        let Some(encrypter) = comm_method.encrypter().map_err(|_| {
+            Error::InvalidConfig(format!(
+                "Invalid encryption key for comm method {}",
+                comm_method.tag()
+            ))
+        })?
+        else {
+            return Ok(result.to_string());
+        };
+        let rejected = |e: verder_helpen_jwt::Error| {
+            log::warn!("Could not re-encrypt authentication result: {e}");
+            Error::BadRequest
+        };
+
+        let Some(allowed) = comm_method.allowed_attributes() else {
+            return verder_helpen_jwt::reencrypt_auth_result(
+                result,
+                self.decrypter.as_ref(),
+                encrypter.as_ref(),
+            )
+            .map_err(rejected);
+        };
+        let filter_keys = self.filter_keys.as_ref().ok_or_else(|| {
+            Error::InvalidConfig("auth_result_keys can not sign results".to_string())
+        })?;
+        let verifier = auth_method
+            .and_then(|m| filter_keys.verifiers.get(m.tag()))
+            .ok_or_else(|| {
+                Error::InvalidConfig("auth_result_keys has no key for the auth method".to_string())
+            })?;
+
+        let mut auth_result = verder_helpen_jwt::decrypt_and_verify_auth_result(
+            result,
+            verifier.as_ref(),
+            self.decrypter.as_ref(),
+        )
+        .map_err(rejected)?;
+        if let Some(attributes) = &mut auth_result.attributes {
+            let mapping = auth_method.map(|m| m.attribute_mapping());
+            attributes.retain(|name, _| {
+                allowed
+                    .iter()
+                    .any(|a| a == name || mapping.and_then(|m| m.get(a)).is_some_and(|m| m == name))
+            });
+        }
+        verder_helpen_jwt::sign_and_encrypt_auth_result(
+            &auth_result,
+            filter_keys.signer.as_ref(),
+            encrypter.as_ref(),
+        )
+        .map_err(rejected)
+    }
+}
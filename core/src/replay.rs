@@ -0,0 +1,201 @@
+use std::{collections::HashMap, sync::Mutex, time::SystemTime};
+
+use rocket_sync_db_pools::postgres;
+
+use crate::{db::CoreDbConn, error::Error};
+
+/// Party that signed a request. IDs only have to be unique per issuer, so one
+/// requestor can not block the IDs of another.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Issuer {
+    /// Requestor of auth-only sessions, by key ID
+    Requestor(String),
+    /// Core itself, for invitations
+    Core,
+}
+
+impl Issuer {
+    // Representation in the database, which can not be mistaken for another
+    // issuer
+    fn key(&self) -> String {
+        match self {
+            Issuer::Requestor(kid) => format!("requestor:{kid}"),
+            Issuer::Core => "core".to_owned(),
+        }
+    }
+}
+
+/// Tenant, issuer and ID of a request
+type RequestKey = (Option<String>, Issuer, String);
+
+/// Keeps track of the IDs of signed start requests until they expire, so each
+/// request can only be used once.
+#[derive(Debug, Default)]
+pub struct ReplayStore {
+    seen: Mutex<HashMap<RequestKey, SystemTime>>,
+}
+
+impl ReplayStore {
+    /// Register the ID of a request, failing if it was used before. When a
+    /// database is available it is used instead of local memory, so replays
+    /// are also detected across core instances.
+    pub async fn register(
+        &self,
+        tenant: Option<&str>,
+        issuer: Issuer,
+        jti: &str,
+        expires_at: SystemTime,
+        db: Option<&CoreDbConn>,
+    ) -> Result<(), Error> {
+        let key = (tenant.map(str::to_owned), issuer, jti.to_owned());
+        let fresh = match db {
+            Some(db) => Self::register_shared(key.clone(), expires_at, db).await?,
+            None => self.register_local(key.clone(), expires_at),
+        };
+
+        if fresh {
+            Ok(())
+        } else {
+            let (_, issuer, jti) = key;
+            log::warn!("Audit: rejected replayed start request from {issuer:?} with jti {jti}");
+            Err(Error::Forbidden("Request was already used".to_owned()))
+        }
+    }
+
+    /// Forget the ID of a request, so it can be used again. Used when the
+    /// request could not be handled after its ID was registered.
+    pub async fn release(
+        &self,
+        tenant: Option<&str>,
+        issuer: Issuer,
+        jti: &str,
+        db: Option<&CoreDbConn>,
+    ) -> Result<(), Error> {
+        match db {
+            Some(db) => {
+                let tenant = tenant.unwrap_or_default().to_owned();
+                let issuer = issuer.key();
+                let jti = jti.to_owned();
+                db.run(move |c| -> Result<u64, postgres::Error> {
+                    c.execute(
+                        "DELETE FROM used_request_id
+                        WHERE tenant = $1 AND issuer = $2 AND jti = $3",
+                        &[&tenant, &issuer, &jti],
+                    )
+                })
+                .await?;
+            }
+            None => {
+                let key = (tenant.map(str::to_owned), issuer, jti.to_owned());
+                self.seen.lock().unwrap().remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    fn register_local(&self, key: RequestKey, expires_at: SystemTime) -> bool {
+        let now = SystemTime::now();
+        let mut seen = self.seen.lock().unwrap();
+
+        // Expired requests are rejected anyway, so there is no need to remember them
+        seen.retain(|_, e| *e > now);
+        if seen.contains_key(&key) {
+            return false;
+        }
+        seen.insert(key, expires_at);
+        true
+    }
+
+    async fn register_shared(
+        (tenant, issuer, jti): RequestKey,
+        expires_at: SystemTime,
+        db: &CoreDbConn,
+    ) -> Result<bool, Error> {
+        // The primary key can not contain nulls
+        let tenant = tenant.unwrap_or_default();
+        let issuer = issuer.key();
+        let n = db
+            .run(move |c| -> Result<u64, postgres::Error> {
+                c.execute("DELETE FROM used_request_id WHERE expires_at < now()", &[])?;
+                c.execute(
+                    "INSERT INTO used_request_id (tenant, issuer, jti, expires_at)
+                    VALUES ($1, $2, $3, $4)
+                    ON CONFLICT DO NOTHING",
+                    &[&tenant, &issuer, &jti, &expires_at],
+                )
+            })
+            .await?;
+
+        Ok(n == 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{Issuer, ReplayStore};
+
+    fn register(
+        store: &ReplayStore,
+        tenant: Option<&str>,
+        issuer: Issuer,
+        jti: &str,
+        expires_at: SystemTime,
+    ) -> bool {
+        tokio_test::block_on(store.register(tenant, issuer, jti, expires_at, None)).is_ok()
+    }
+
+    #[test]
+    fn test_replay_local() {
+        let store = ReplayStore::default();
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+        let requestor = || Issuer::Requestor("a".to_owned());
+
+        assert!(register(&store, None, requestor(), "a", expires_at));
+        assert!(register(&store, None, requestor(), "b", expires_at));
+        assert!(!register(&store, None, requestor(), "a", expires_at));
+    }
+
+    #[test]
+    fn test_replay_scopes() {
+        let store = ReplayStore::default();
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+        let requestor = |kid: &str| Issuer::Requestor(kid.to_owned());
+
+        // The same ID from another requestor, tenant or core is not a replay
+        assert!(register(&store, None, requestor("a"), "x", expires_at));
+        assert!(register(&store, None, requestor("b"), "x", expires_at));
+        assert!(register(&store, Some("t"), requestor("a"), "x", expires_at));
+        assert!(register(&store, None, Issuer::Core, "x", expires_at));
+        assert!(!register(&store, None, requestor("b"), "x", expires_at));
+        assert!(!register(
+            &store,
+            Some("t"),
+            requestor("a"),
+            "x",
+            expires_at
+        ));
+    }
+
+    #[test]
+    fn test_replay_release() {
+        let store = ReplayStore::default();
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+
+        assert!(register(&store, None, Issuer::Core, "a", expires_at));
+        tokio_test::block_on(store.release(None, Issuer::Core, "a", None)).unwrap();
+        assert!(register(&store, None, Issuer::Core, "a", expires_at));
+        assert!(!register(&store, None, Issuer::Core, "a", expires_at));
+    }
+
+    #[test]
+    fn test_replay_local_pruning() {
+        let store = ReplayStore::default();
+        let expired = SystemTime::now() - Duration::from_secs(1);
+
+        assert!(register(&store, None, Issuer::Core, "a", expired));
+        assert!(register(&store, None, Issuer::Core, "b", expired));
+        assert!(store.seen.lock().unwrap().len() <= 1);
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use rocket::{serde::json::Json, State};
+use rocket_sync_db_pools::postgres;
+use serde::Serialize;
+
+use crate::{
+    config::CoreConfig,
+    db::{CoreDbConn, SharedDb},
+    error::Error,
+};
+
+// How long sessions are kept after they were started
+const SESSION_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Generate a fresh, unguessable session ID
+pub fn new_session_id() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    /// Plugins accepted the session and the user was sent on their way
+    Started,
+    /// The user continued the session on another device
+    HandedOff,
+    /// Authentication results were forwarded to the attribute url
+    AttributesDelivered,
+    /// Authentication results are queued for delivery to the attribute url
+    AttributesQueued,
+    /// Authentication results could not be forwarded to the attribute url
+    AttributesFailed,
+}
+
+impl SessionState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SessionState::Started => "started",
+            SessionState::HandedOff => "handed_off",
+            SessionState::AttributesDelivered => "attributes_delivered",
+            SessionState::AttributesQueued => "attributes_queued",
+            SessionState::AttributesFailed => "attributes_failed",
+        }
+    }
+
+    fn parse(state: &str) -> Option<Self> {
+        match state {
+            "started" => Some(SessionState::Started),
+            "handed_off" => Some(SessionState::HandedOff),
+            "attributes_delivered" => Some(SessionState::AttributesDelivered),
+            "attributes_queued" => Some(SessionState::AttributesQueued),
+            "attributes_failed" => Some(SessionState::AttributesFailed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct Transition {
+    pub state: SessionState,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionRecord {
+    pub id: String,
+    /// Tenant the session was started for, only visible to that tenant
+    #[serde(skip)]
+    pub tenant: Option<String>,
+    pub purpose: String,
+    pub auth_method: Option<String>,
+    pub comm_method: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub transitions: Vec<Transition>,
+}
+
+impl SessionRecord {
+    pub fn new(
+        id: String,
+        tenant: Option<&str>,
+        purpose: &str,
+        auth_method: Option<&str>,
+        comm_method: Option<&str>,
+    ) -> Self {
+        let now = Utc::now();
+        SessionRecord {
+            id,
+            tenant: tenant.map(str::to_owned),
+            purpose: purpose.to_owned(),
+            auth_method: auth_method.map(str::to_owned),
+            comm_method: comm_method.map(str::to_owned),
+            created_at: now,
+            transitions: vec![Transition {
+                state: SessionState::Started,
+                at: now,
+            }],
+        }
+    }
+
+    /// Most recently observed state of the session
+    pub fn status(&self) -> SessionState {
+        self.transitions
+            .last()
+            .map(|t| t.state)
+            .unwrap_or(SessionState::Started)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionStatus {
+    status: SessionState,
+    #[serde(flatten)]
+    session: SessionRecord,
+}
+
+/// Keeps track of the sessions started through this core. When a database is
+/// available it is used instead of local memory, so sessions can be queried
+/// on any core instance.
+#[derive(Debug, Default, Clone)]
+pub struct SessionRegistry {
+    sessions: Arc<Mutex<HashMap<String, SessionRecord>>>,
+}
+
+impl SessionRegistry {
+    pub async fn create(
+        &self,
+        session: SessionRecord,
+        db: Option<&CoreDbConn>,
+    ) -> Result<(), Error> {
+        match db {
+            Some(db) => Self::create_shared(session, db).await,
+            None => {
+                let cutoff = Utc::now() - SESSION_RETENTION;
+                let mut sessions = self.sessions.lock().unwrap();
+                sessions.retain(|_, s| s.created_at > cutoff);
+                sessions.insert(session.id.clone(), session);
+                Ok(())
+            }
+        }
+    }
+
+    /// Record a state transition observed for a session. Transitions for
+    /// unknown sessions are ignored.
+    pub async fn record(
+        &self,
+        id: &str,
+        state: SessionState,
+        db: Option<&CoreDbConn>,
+    ) -> Result<(), Error> {
+        let transition = Transition {
+            state,
+            at: Utc::now(),
+        };
+        match db {
+            Some(db) => Self::record_shared(id.to_owned(), transition, db).await,
+            None => {
+                if let Some(session) = self.sessions.lock().unwrap().get_mut(id) {
+                    session.transitions.push(transition);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn get(
+        &self,
+        id: &str,
+        db: Option<&CoreDbConn>,
+    ) -> Result<Option<SessionRecord>, Error> {
+        match db {
+            Some(db) => Self::get_shared(id.to_owned(), db).await,
+            None => Ok(self.sessions.lock().unwrap().get(id).cloned()),
+        }
+    }
+
+    async fn create_shared(session: SessionRecord, db: &CoreDbConn) -> Result<(), Error> {
+        db.run(move |c| -> Result<(), postgres::Error> {
+            let mut transaction = c.transaction()?;
+            transaction.execute(
+                "DELETE FROM session WHERE created_at < $1",
+                &[&(SystemTime::now() - SESSION_RETENTION)],
+            )?;
+            transaction.execute(
+                "INSERT INTO session (id, tenant, purpose, auth_method, comm_method, created_at)
+                VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &session.id,
+                    &session.tenant,
+                    &session.purpose,
+                    &session.auth_method,
+                    &session.comm_method,
+                    &SystemTime::from(session.created_at),
+                ],
+            )?;
+            for transition in &session.transitions {
+                transaction.execute(
+                    "INSERT INTO session_transition (session_id, state, at) VALUES ($1, $2, $3)",
+                    &[
+                        &session.id,
+                        &transition.state.as_str(),
+                        &SystemTime::from(transition.at),
+                    ],
+                )?;
+            }
+            transaction.commit()
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_shared(
+        id: String,
+        transition: Transition,
+        db: &CoreDbConn,
+    ) -> Result<(), Error> {
+        db.run(move |c| -> Result<u64, postgres::Error> {
+            c.execute(
+                "INSERT INTO session_transition (session_id, state, at)
+                SELECT id, $2::text, $3::timestamptz FROM session WHERE id = $1",
+                &[
+                    &id,
+                    &transition.state.as_str(),
+                    &SystemTime::from(transition.at),
+                ],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_shared(id: String, db: &CoreDbConn) -> Result<Option<SessionRecord>, Error> {
+        Ok(db
+            .run(move |c| -> Result<Option<SessionRecord>, postgres::Error> {
+                let session = match c.query_opt(
+                    "SELECT tenant, purpose, auth_method, comm_method, created_at
+                    FROM session WHERE id = $1",
+                    &[&id],
+                )? {
+                    Some(session) => session,
+                    None => return Ok(None),
+                };
+                let transitions = c
+                    .query(
+                        "SELECT state, at FROM session_transition
+                        WHERE session_id = $1 ORDER BY at",
+                        &[&id],
+                    )?
+                    .iter()
+                    .filter_map(|row| {
+                        Some(Transition {
+                            state: SessionState::parse(row.get(0))?,
+                            at: row.get::<_, SystemTime>(1).into(),
+                        })
+                    })
+                    .collect();
+
+                Ok(Some(SessionRecord {
+                    id,
+                    tenant: session.get(0),
+                    purpose: session.get(1),
+                    auth_method: session.get(2),
+                    comm_method: session.get(3),
+                    created_at: session.get::<_, SystemTime>(4).into(),
+                    transitions,
+                }))
+            })
+            .await?)
+    }
+}
+
+#[get("/session/<id>")]
+pub async fn session_status(
+    id: String,
+    config: &CoreConfig,
+    sessions: &State<SessionRegistry>,
+    db: SharedDb,
+) -> Result<Json<SessionStatus>, Error> {
+    let session = sessions
+        .get(&id, db.0.as_ref())
+        .await?
+        .filter(|session| session.tenant.as_deref() == config.tenant())
+        .ok_or(Error::NoSuchSession(id))?;
+
+    Ok(Json(SessionStatus {
+        status: session.status(),
+        session,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{new_session_id, SessionRecord, SessionRegistry, SessionState};
+
+    #[test]
+    fn test_session_registry_local() {
+        let registry = SessionRegistry::default();
+        let id = new_session_id();
+
+        tokio_test::block_on(registry.create(
+            SessionRecord::new(id.clone(), None, "test", Some("irma"), None),
+            None,
+        ))
+        .unwrap();
+        let session = tokio_test::block_on(registry.get(&id, None))
+            .unwrap()
+            .unwrap();
+        assert_eq!(session.purpose, "test");
+        assert_eq!(session.auth_method.as_deref(), Some("irma"));
+        assert_eq!(session.comm_method, None);
+        assert_eq!(session.status(), SessionState::Started);
+
+        tokio_test::block_on(registry.record(&id, SessionState::AttributesDelivered, None))
+            .unwrap();
+        let session = tokio_test::block_on(registry.get(&id, None))
+            .unwrap()
+            .unwrap();
+        assert_eq!(session.transitions.len(), 2);
+        assert_eq!(session.status(), SessionState::AttributesDelivered);
+
+        // Unknown sessions are not created by recording a transition
+        tokio_test::block_on(registry.record("unknown", SessionState::AttributesFailed, None))
+            .unwrap();
+        assert!(tokio_test::block_on(registry.get("unknown", None))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use rocket_sync_db_pools::postgres;
+
+use crate::{db::CoreDbConn, error::Error, session::new_session_id};
+
+/// Tenant, kind and ID of a stored value
+type StashKey = (Option<String>, &'static str, String);
+
+/// Short-lived values that are referred to by a random ID, so they do not
+/// have to be passed around in urls themselves. When a database is
+/// available it is used instead of local memory, so all instances of core
+/// see the same values.
+#[derive(Debug, Default)]
+pub struct Stash {
+    values: Mutex<HashMap<StashKey, (String, SystemTime)>>,
+}
+
+impl Stash {
+    /// Store a value of the given kind for `ttl`, returning its ID
+    pub async fn put(
+        &self,
+        tenant: Option<&str>,
+        kind: &'static str,
+        value: String,
+        ttl: Duration,
+        db: Option<&CoreDbConn>,
+    ) -> Result<String, Error> {
+        let id = new_session_id();
+        let expires_at = SystemTime::now() + ttl;
+        match db {
+            Some(db) => {
+                let tenant = tenant.unwrap_or_default().to_owned();
+                let id = id.clone();
+                db.run(move |c| -> Result<u64, postgres::Error> {
+                    c.execute("DELETE FROM stash WHERE expires_at < now()", &[])?;
+                    c.execute(
+                        "INSERT INTO stash (tenant, kind, id, value, expires_at)
+                        VALUES ($1, $2, $3, $4, $5)",
+                        &[&tenant, &kind, &id, &value, &expires_at],
+                    )
+                })
+                .await?;
+            }
+            None => {
+                let now = SystemTime::now();
+                let mut values = self.values.lock().unwrap();
+                values.retain(|_, (_, e)| *e > now);
+                values.insert(
+                    (tenant.map(str::to_owned), kind, id.clone()),
+                    (value, expires_at),
+                );
+            }
+        }
+
+        Ok(id)
+    }
+
+    /// Look up a value that has not expired yet
+    pub async fn get(
+        &self,
+        tenant: Option<&str>,
+        kind: &'static str,
+        id: &str,
+        db: Option<&CoreDbConn>,
+    ) -> Result<Option<String>, Error> {
+        match db {
+            Some(db) => {
+                let tenant = tenant.unwrap_or_default().to_owned();
+                let id = id.to_owned();
+                Ok(db
+                    .run(move |c| -> Result<Option<String>, postgres::Error> {
+                        Ok(c.query_opt(
+                            "SELECT value FROM stash
+                            WHERE tenant = $1 AND kind = $2 AND id = $3 AND expires_at >= now()",
+                            &[&tenant, &kind, &id],
+                        )?
+                        .map(|row| row.get(0)))
+                    })
+                    .await?)
+            }
+            None => {
+                let key = (tenant.map(str::to_owned), kind, id.to_owned());
+                Ok(self
+                    .values
+                    .lock()
+                    .unwrap()
+                    .get(&key)
+                    .filter(|(_, expires_at)| *expires_at > SystemTime::now())
+                    .map(|(value, _)| value.clone()))
+            }
+        }
+    }
+
+    /// Look up a value that has not expired yet and remove it, so it can be
+    /// used only once
+    pub async fn take(
+        &self,
+        tenant: Option<&str>,
+        kind: &'static str,
+        id: &str,
+        db: Option<&CoreDbConn>,
+    ) -> Result<Option<String>, Error> {
+        match db {
+            Some(db) => {
+                let tenant = tenant.unwrap_or_default().to_owned();
+                let id = id.to_owned();
+                Ok(db
+                    .run(move |c| -> Result<Option<String>, postgres::Error> {
+                        Ok(c.query_opt(
+                            "DELETE FROM stash
+                            WHERE tenant = $1 AND kind = $2 AND id = $3
+                            RETURNING value, expires_at >= now()",
+                            &[&tenant, &kind, &id],
+                        )?
+                        .filter(|row| row.get(1))
+                        .map(|row| row.get(0)))
+                    })
+                    .await?)
+            }
+            None => {
+                let key = (tenant.map(str::to_owned), kind, id.to_owned());
+                Ok(self
+                    .values
+                    .lock()
+                    .unwrap()
+                    .remove(&key)
+                    .filter(|(_, expires_at)| *expires_at > SystemTime::now())
+                    .map(|(value, _)| value))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Stash;
+
+    #[test]
+    fn test_stash_local() {
+        let stash = Stash::default();
+        let ttl = Duration::from_secs(60);
+
+        let id = tokio_test::block_on(stash.put(None, "a", "value".into(), ttl, None)).unwrap();
+        let get = |tenant, kind| tokio_test::block_on(stash.get(tenant, kind, &id, None)).unwrap();
+        assert_eq!(get(None, "a"), Some("value".into()));
+
+        // Values are separated by tenant and kind
+        assert_eq!(get(Some("t"), "a"), None);
+        assert_eq!(get(None, "b"), None);
+
+        let expired =
+            tokio_test::block_on(stash.put(None, "a", "value".into(), Duration::ZERO, None))
+                .unwrap();
+        assert_eq!(
+            tokio_test::block_on(stash.get(None, "a", &expired, None)).unwrap(),
+            None
+        );
+
+        // Taken values are gone afterwards
+        let take = |id| tokio_test::block_on(stash.take(None, "a", id, None)).unwrap();
+        assert_eq!(take(&id), Some("value".into()));
+        assert_eq!(take(&id), None);
+        assert_eq!(take(&expired), None);
+    }
+}
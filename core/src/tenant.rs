@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use rocket::{
+    figment::{self, Figment},
+    http::Status,
+    outcome::Outcome,
+    request::{self, FromRequest, Request},
+};
+use serde::Deserialize;
+
+use crate::config::CoreConfig;
+
+/// How requests are matched to a tenant
+#[derive(Debug, Deserialize)]
+struct TenantRouting {
+    /// Host names on which the tenant is served
+    #[serde(default)]
+    hosts: Vec<String>,
+    /// Path under which the tenant is served, e.g. "/amsterdam"
+    path_prefix: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Tenant {
+    hosts: Vec<String>,
+    path_prefix: Option<String>,
+    config: CoreConfig,
+}
+
+impl Tenant {
+    fn matches_path(&self, path: &str) -> bool {
+        self.path_prefix.as_ref().is_some_and(|prefix| {
+            path.strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+
+    fn matches_host(&self, host: &str) -> bool {
+        self.hosts.iter().any(|h| h.eq_ignore_ascii_case(host))
+    }
+}
+
+/// Configuration of all organisations served by this core. Without tenants
+/// configured, every request is served with the global configuration.
+#[derive(Debug)]
+pub enum Tenants {
+    Single(Box<CoreConfig>),
+    Multiple(Vec<Tenant>),
+}
+
+fn normalize_prefix(tenant: &str, prefix: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    if !prefix.starts_with('/') || prefix.len() < 2 {
+        log::error!("Invalid path prefix {prefix} for tenant {tenant}");
+        panic!("Invalid path prefix {prefix} for tenant {tenant}")
+    }
+    prefix.to_string()
+}
+
+pub fn tenants_configured(figment: &Figment) -> bool {
+    figment.find_value("tenants").is_ok()
+}
+
+/// Path prefixes under which the routes of tenants need to be mounted
+pub fn tenant_path_prefixes(figment: &Figment) -> Vec<String> {
+    figment
+        .extract_inner::<HashMap<String, TenantRouting>>("tenants")
+        .map(|tenants| {
+            tenants
+                .into_iter()
+                .filter_map(|(tenant, routing)| {
+                    routing
+                        .path_prefix
+                        .map(|prefix| normalize_prefix(&tenant, &prefix))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl Tenants {
+    pub fn extract(figment: &Figment) -> Result<Self, Box<figment::Error>> {
+        if !tenants_configured(figment) {
+            return Ok(Tenants::Single(Box::new(figment.extract()?)));
+        }
+
+        let routing = figment.extract_inner::<HashMap<String, TenantRouting>>("tenants")?;
+        let mut tenants = vec![];
+        for (tenant, routing) in routing {
+            if routing.hosts.is_empty() && routing.path_prefix.is_none() {
+                log::error!("Tenant {tenant} has neither hosts nor a path prefix");
+                panic!("Tenant {tenant} has neither hosts nor a path prefix")
+            }
+            let config = figment
+                .extract_inner::<CoreConfig>(&format!("tenants.{tenant}"))?
+                .with_tenant(tenant.clone());
+            tenants.push(Tenant {
+                hosts: routing.hosts,
+                path_prefix: routing
+                    .path_prefix
+                    .map(|prefix| normalize_prefix(&tenant, &prefix)),
+                config,
+            });
+        }
+
+        Ok(Tenants::Multiple(tenants))
+    }
+
+    /// Configuration of the tenant a request is for
+    pub fn for_request(&self, request: &Request<'_>) -> Option<&CoreConfig> {
+        let host = request.host().map(|h| h.domain().as_str());
+        self.resolve(host, request.uri().path().as_str())
+    }
+
+    /// Configuration of the tenant a request is for. Path prefixes take
+    /// precedence over host names.
+    fn resolve(&self, host: Option<&str>, path: &str) -> Option<&CoreConfig> {
+        match self {
+            Tenants::Single(config) => Some(config.as_ref()),
+            Tenants::Multiple(tenants) => tenants
+                .iter()
+                .filter(|t| t.matches_path(path))
+                .max_by_key(|t| t.path_prefix.as_ref().map(String::len))
+                .or_else(|| {
+                    let host = host?;
+                    tenants.iter().find(|t| t.matches_host(host))
+                })
+                .map(|t| &t.config),
+        }
+    }
+}
+
+// Requests that can not be matched to a tenant are answered with 404, so
+// nothing is revealed about other tenants
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r CoreConfig {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let Some(tenants) = request.rocket().state::<Tenants>() else {
+            log::error!("Tenant configuration is not available");
+            return Outcome::Error((Status::InternalServerError, ()));
+        };
+
+        match tenants.for_request(request) {
+            Some(config) => Outcome::Success(config),
+            None => {
+                let host = request.host().map(|h| h.domain().as_str());
+                log::warn!("No tenant for request to {:?}{}", host, request.uri());
+                Outcome::Error((Status::NotFound, ()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::providers::{Format, Toml};
+    use rocket::{
+        figment::Figment,
+        http::{uri::Host, Status},
+        local::blocking::Client,
+    };
+    use serde_json::Value;
+
+    use crate::setup_routes;
+
+    const TENANT: &str = r#"
+server_url = "https://core.verderhelpen.nl"
+internal_secret = "sample_secret_1234567890178901237890"
+authonly_request_keys = {}
+
+[[auth_methods]]
+tag = "irma"
+name = "Gebruik je IRMA app"
+image_path = "/static/irma.svg"
+start = "http://auth-irma:8000"
+
+[[comm_methods]]
+tag = "call"
+name = "Bellen"
+image_path = "/static/phone.svg"
+start = "http://comm-test:8000"
+
+[[purposes]]
+tag = "report_move"
+attributes = [ "email" ]
+allowed_auth = [ "*" ]
+allowed_comm = [ "*" ]
+"#;
+
+    fn tenant(name: &str, routing: &str, purpose: &str) -> String {
+        format!(
+            "[global.tenants.{name}]\n{routing}\n{}",
+            TENANT
+                .replace("report_move", purpose)
+                .replace("[[", &format!("[[global.tenants.{name}."))
+        )
+    }
+
+    fn tenant_client() -> Client {
+        let config = format!(
+            "{}\n{}",
+            tenant(
+                "amsterdam",
+                "hosts = [ \"amsterdam.example.com\" ]",
+                "report_move"
+            ),
+            tenant("utrecht", "path_prefix = \"/utrecht\"", "request_permit"),
+        );
+        let figment = Figment::from(rocket::Config::default())
+            .select(rocket::Config::DEFAULT_PROFILE)
+            .merge(Toml::string(&config).nested());
+        Client::tracked(setup_routes(rocket::custom(figment))).unwrap()
+    }
+
+    fn purposes(client: &Client, host: &str, path: &str) -> Option<Vec<String>> {
+        let mut request = client.get(path.to_string());
+        request
+            .inner_mut()
+            .set_host(Host::parse_owned(host.to_string()).unwrap());
+        let response = request.dispatch();
+        if response.status() != Status::Ok {
+            return None;
+        }
+        let options = response.into_json::<Value>().unwrap();
+        Some(options.as_object().unwrap().keys().cloned().collect())
+    }
+
+    #[test]
+    fn test_tenant_by_host() {
+        let client = tenant_client();
+
+        assert_eq!(
+            purposes(&client, "amsterdam.example.com", "/session_options"),
+            Some(vec!["report_move".to_string()])
+        );
+        assert_eq!(
+            purposes(&client, "other.example.com", "/session_options"),
+            None
+        );
+        assert_eq!(
+            purposes(
+                &client,
+                "amsterdam.example.com",
+                "/session_options/request_permit"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tenant_by_path_prefix() {
+        let client = tenant_client();
+
+        assert_eq!(
+            purposes(&client, "core.example.com", "/utrecht/session_options"),
+            Some(vec!["request_permit".to_string()])
+        );
+        // The path prefix takes precedence over the host
+        assert_eq!(
+            purposes(&client, "amsterdam.example.com", "/utrecht/session_options"),
+            Some(vec!["request_permit".to_string()])
+        );
+        assert_eq!(
+            purposes(&client, "core.example.com", "/utrechtse/session_options"),
+            None
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use verder_helpen_proto::{body_signature, Correlation};
+
+use crate::{config::TokenSecret, error::Error, methods::http_client};
+
+pub use verder_helpen_proto::SIGNATURE_HEADER;
+pub const EVENT_HEADER: &str = "X-Verder-Helpen-Event";
+
+fn default_attempts() -> u32 {
+    5
+}
+
+fn default_backoff() -> u64 {
+    1000
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// Plugins accepted a new session
+    SessionStarted,
+    /// Starting a session failed
+    SessionStartFailed,
+    /// The attribute url shim delivered authentication results
+    AuthResultDelivered,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::SessionStarted => "session_started",
+            WebhookEvent::SessionStartFailed => "session_start_failed",
+            WebhookEvent::AuthResultDelivered => "auth_result_delivered",
+        }
+    }
+}
+
+/// Subscription of an external system to session lifecycle events
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookConfig {
+    url: String,
+    /// Secret with which the payloads are signed
+    secret: TokenSecret,
+    /// Purposes to notify about, or `*` for all of them
+    purposes: Vec<String>,
+    /// Events to notify about, all when empty
+    #[serde(default)]
+    events: Vec<WebhookEvent>,
+    /// Number of delivery attempts
+    #[serde(default = "default_attempts")]
+    attempts: u32,
+    /// Delay before the first retry in milliseconds, doubled on every retry
+    #[serde(default = "default_backoff")]
+    backoff: u64,
+}
+
+impl WebhookConfig {
+    /// Payloads are signed with HMAC-SHA256, which needs a long enough key
+    pub fn check_secret(&self) -> Result<(), Error> {
+        if self.secret.as_bytes().len() < TokenSecret::MIN_HMAC_LEN {
+            return Err(Error::InvalidConfig(format!(
+                "Secret of webhook {} must be at least {} bytes",
+                self.url,
+                TokenSecret::MIN_HMAC_LEN
+            )));
+        }
+        Ok(())
+    }
+
+    fn subscribed(&self, event: WebhookEvent, purpose: &str) -> bool {
+        (self.events.is_empty() || self.events.contains(&event))
+            && self.purposes.iter().any(|p| p == "*" || p == purpose)
+    }
+}
+
+/// Lifecycle event of a session. Only identifiers are included, never any
+/// personal data.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionEvent {
+    pub event: WebhookEvent,
+    pub purpose: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comm_method: Option<String>,
+    /// Error code for failed starts, as in the problem details of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    /// Unique ID of the notification, the same for all delivery attempts
+    id: String,
+    #[serde(flatten)]
+    event: &'a SessionEvent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant: Option<&'a str>,
+    request_id: &'a str,
+    at: DateTime<Utc>,
+}
+
+/// Signature header value for a payload sent at the given unix timestamp,
+/// an HMAC-SHA256 over `<timestamp>.<body>`
+pub fn signature(secret: &TokenSecret, timestamp: u64, body: &str) -> Result<String, Error> {
+    Ok(body_signature(secret.as_bytes(), timestamp, body)?)
+}
+
+async fn deliver(
+    client: &reqwest::Client,
+    webhook: &WebhookConfig,
+    event: WebhookEvent,
+    body: &str,
+) {
+    let mut backoff = Duration::from_millis(webhook.backoff);
+    for attempt in 1..=webhook.attempts {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let signature = match signature(&webhook.secret, timestamp, body) {
+            Ok(signature) => signature,
+            Err(e) => {
+                log::error!("Could not sign webhook payload: {e}");
+                return;
+            }
+        };
+        let result = client
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .header(EVENT_HEADER, event.as_str())
+            .body(body.to_owned())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => return,
+            Err(e) => log::warn!(
+                "Attempt {attempt} to deliver {} webhook to {} failed: {e}",
+                event.as_str(),
+                webhook.url
+            ),
+        }
+        if attempt < webhook.attempts {
+            rocket::tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+    log::error!(
+        "Giving up on delivering {} webhook to {}",
+        event.as_str(),
+        webhook.url
+    );
+}
+
+/// Send an event to all subscribed webhooks. Delivery happens in the
+/// background, so the user is never kept waiting.
+pub fn notify(
+    webhooks: &[WebhookConfig],
+    tenant: Option<&str>,
+    event: SessionEvent,
+    correlation: &Correlation,
+) {
+    let subscribed: Vec<WebhookConfig> = webhooks
+        .iter()
+        .filter(|webhook| webhook.subscribed(event.event, &event.purpose))
+        .cloned()
+        .collect();
+    if subscribed.is_empty() {
+        return;
+    }
+
+    let payload = WebhookPayload {
+        id: format!("{:032x}", rand::random::<u128>()),
+        event: &event,
+        tenant,
+        request_id: correlation.request_id(),
+        at: Utc::now(),
+    };
+    let (body, client) = match (serde_json::to_string(&payload), http_client(correlation)) {
+        (Ok(body), Ok(client)) => (body, client),
+        (Err(e), _) => return log::error!("Could not serialize webhook payload: {e}"),
+        (_, Err(e)) => return log::error!("Could not create client for webhooks: {e}"),
+    };
+
+    for webhook in subscribed {
+        let (client, body) = (client.clone(), body.clone());
+        rocket::tokio::spawn(async move { deliver(&client, &webhook, event.event, &body).await });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+    use httpmock::{Mock, MockServer};
+    use rocket::{
+        http::{Accept, ContentType},
+        local::blocking::Client,
+    };
+    use serde_json::json;
+
+    use super::{signature, WebhookConfig};
+    use crate::{config::TokenSecret, setup_routes};
+
+    fn webhook_client(server: &MockServer) -> Client {
+        let figment = Figment::from(rocket::Config::default())
+            .select(rocket::Config::DEFAULT_PROFILE)
+            .merge(
+                Toml::string(&format!(
+                    r#"
+[global]
+server_url = ""
+internal_url = ""
+internal_secret = "sample_secret_1234567890178901237890"
+authonly_request_keys = {{}}
+track_sessions = true
+
+[[global.webhooks]]
+url = "{0}/crm"
+secret = "webhook_secret_1234567890123456789"
+purposes = [ "test" ]
+events = [ "session_started", "session_start_failed" ]
+backoff = 10
+
+[[global.webhooks]]
+url = "{0}/other"
+secret = "webhook_secret_1234567890123456789"
+purposes = [ "other" ]
+
+[[global.auth_methods]]
+tag = "test"
+name = "test"
+image_path = "none"
+start = "{0}/auth"
+
+[[global.comm_methods]]
+tag = "test"
+name = "test"
+image_path = "none"
+start = "{0}/comm"
+
+[[global.purposes]]
+tag = "test"
+attributes = [ "email" ]
+allowed_auth = [ "test" ]
+allowed_comm = [ "test" ]
+"#,
+                    server.base_url(),
+                ))
+                .nested(),
+            );
+        Client::tracked(setup_routes(rocket::custom(figment))).unwrap()
+    }
+
+    // Webhooks are delivered in the background
+    fn wait_for_hits(mock: &Mock, hits: usize) {
+        let start = Instant::now();
+        while mock.hits() < hits && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        mock.assert_hits(hits);
+    }
+
+    fn start(client: &Client) -> rocket::http::Status {
+        client
+            .post("/start")
+            .header(ContentType::JSON)
+            .header(Accept::JSON)
+            .body(r#"{"purpose":"test","auth_method":"test","comm_method":"test"}"#)
+            .dispatch()
+            .status()
+    }
+
+    #[test]
+    fn test_webhook_session_started() {
+        let server = MockServer::start();
+        let client = webhook_client(&server);
+        server.mock(|when, then| {
+            when.path("/comm/start_communication");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({ "client_url": "https://example.com/continuation" }));
+        });
+        server.mock(|when, then| {
+            when.path("/auth/start_authentication");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({ "client_url": "https://example.com/auth" }));
+        });
+        let webhook = server.mock(|when, then| {
+            when.path("/crm")
+                .header("X-Verder-Helpen-Event", "session_started")
+                .header_exists("X-Verder-Helpen-Signature")
+                .json_body_partial(
+                    json!({
+                        "event": "session_started",
+                        "purpose": "test",
+                        "auth_method": "test",
+                        "comm_method": "test",
+                    })
+                    .to_string(),
+                );
+            then.status(200);
+        });
+        let other = server.mock(|when, then| {
+            when.path("/other");
+            then.status(200);
+        });
+
+        assert_eq!(start(&client), rocket::http::Status::Ok);
+        wait_for_hits(&webhook, 1);
+        other.assert_hits(0);
+    }
+
+    #[test]
+    fn test_webhook_session_start_failed() {
+        let server = MockServer::start();
+        let client = webhook_client(&server);
+        server.mock(|when, then| {
+            when.path("/comm/start_communication");
+            then.status(503);
+        });
+        // Failing deliveries are retried
+        let webhook = server.mock(|when, then| {
+            when.path("/crm").json_body_partial(
+                json!({
+                    "event": "session_start_failed",
+                    "purpose": "test",
+                    "error": "plugin_failure",
+                })
+                .to_string(),
+            );
+            then.status(500);
+        });
+
+        assert_ne!(start(&client), rocket::http::Status::Ok);
+        wait_for_hits(&webhook, 5);
+    }
+
+    #[test]
+    fn test_signature() {
+        let secret = TokenSecret::from("webhook_secret_1234567890123456789".to_string());
+        let first = signature(&secret, 1700000000, "{}").unwrap();
+        assert!(first.starts_with("t=1700000000,v1="));
+        assert_eq!(first.len(), "t=1700000000,v1=".len() + 64);
+        assert_eq!(first, signature(&secret, 1700000000, "{}").unwrap());
+        assert_ne!(first, signature(&secret, 1700000001, "{}").unwrap());
+        assert_ne!(first, signature(&secret, 1700000000, "{ }").unwrap());
+    }
+
+    #[test]
+    fn test_short_secret() {
+        let webhook = |secret: &str| {
+            serde_json::from_value::<WebhookConfig>(json!({
+                "url": "https://crm.example.com",
+                "secret": secret,
+                "purposes": ["*"],
+            }))
+            .unwrap()
+        };
+        assert!(webhook("webhook_secret").check_secret().is_err());
+        assert!(webhook("webhook_secret_1234567890123456789")
+            .check_secret()
+            .is_ok());
+    }
+}
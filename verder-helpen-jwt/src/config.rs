@@ -10,7 +10,7 @@ use crate::error::Error;
 
 // Configuration management
 //
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct InnerKeyConfig {
     key: String,
 }
@@ -24,7 +24,7 @@ impl Debug for InnerKeyConfig {
 /// Parsable configuration describing an encryption key.
 /// This can be cast (using try_from) into the JweDecryptor en JweEncryptor
 /// types needed by the jwe functions.
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum EncryptionKeyConfig {
     RSA(InnerKeyConfig),
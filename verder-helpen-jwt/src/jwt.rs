@@ -35,6 +35,11 @@ pub fn sign_and_encrypt_auth_result(
 
     let jws = jwt::encode_with_signer(&sig_payload, &sig_header, signer)?;
 
+    encrypt_signed_auth_result(&jws, encrypter)
+}
+
+// Wrap a signed auth result in a jwe
+fn encrypt_signed_auth_result(jws: &str, encrypter: &dyn JweEncrypter) -> Result<String, Error> {
     let mut enc_header = JweHeader::new();
     enc_header.set_token_type("JWT");
     enc_header.set_content_type("JWT");
@@ -49,19 +54,36 @@ pub fn sign_and_encrypt_auth_result(
     )?)
 }
 
+// Extract the signed auth result from a jwe
+fn decrypt_signed_auth_result(jwe: &str, decrypter: &dyn JweDecrypter) -> Result<String, Error> {
+    let decoded_jwe = jwt::decode_with_decrypter(jwe, decrypter)?.0;
+    Ok(decoded_jwe
+        .claim("njwt")
+        .ok_or(Error::InvalidStructure)?
+        .as_str()
+        .ok_or(Error::InvalidStructure)?
+        .to_string())
+}
+
+/// Encrypt an auth result for another recipient, leaving the signed contents
+/// untouched. The signature is not checked, that is left to the recipient.
+pub fn reencrypt_auth_result(
+    jwe: &str,
+    decrypter: &dyn JweDecrypter,
+    encrypter: &dyn JweEncrypter,
+) -> Result<String, Error> {
+    let jws = decrypt_signed_auth_result(jwe, decrypter)?;
+    encrypt_signed_auth_result(&jws, encrypter)
+}
+
 fn raw_decrypt_and_verify_auth_result(
     jwe: &str,
     validator: &dyn JwsVerifier,
     decrypter: &dyn JweDecrypter,
     do_time_validation: bool,
 ) -> Result<AuthResult, Error> {
-    let decoded_jwe = jwt::decode_with_decrypter(jwe, decrypter)?.0;
-    let jws = decoded_jwe
-        .claim("njwt")
-        .ok_or(Error::InvalidStructure)?
-        .as_str()
-        .ok_or(Error::InvalidStructure)?;
-    let decoded_jws = jwt::decode_with_verifier(jws, validator)?.0;
+    let jws = decrypt_signed_auth_result(jwe, decrypter)?;
+    let decoded_jws = jwt::decode_with_verifier(&jws, validator)?.0;
     if do_time_validation {
         let mut validator = JwtPayloadValidator::new();
         validator.set_base_time(std::time::SystemTime::now());
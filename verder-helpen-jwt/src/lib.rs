@@ -9,7 +9,7 @@ pub use config::{EncryptionKeyConfig, SignKeyConfig};
 pub use error::Error;
 pub use jwt::{
     dangerous_decrypt_auth_result_without_verifying_expiration, decrypt_and_verify_auth_result,
-    sign_and_encrypt_auth_result,
+    reencrypt_auth_result, sign_and_encrypt_auth_result,
 };
 
 // Tests
@@ -205,6 +205,56 @@ mod tests {
         assert_eq!(in_result, out_result);
     }
 
+    #[test]
+    fn reencrypt_test() {
+        let rsa_encrypter = Box::<dyn JweEncrypter>::try_from(
+            serde_yaml::from_str::<EncryptionKeyConfig>(RSA_PUBKEY).unwrap(),
+        )
+        .unwrap();
+        let rsa_decrypter = Box::<dyn JweDecrypter>::try_from(
+            serde_yaml::from_str::<EncryptionKeyConfig>(RSA_PRIVKEY).unwrap(),
+        )
+        .unwrap();
+        let ec_encrypter = Box::<dyn JweEncrypter>::try_from(
+            serde_yaml::from_str::<EncryptionKeyConfig>(EC_PUBKEY).unwrap(),
+        )
+        .unwrap();
+        let ec_decrypter = Box::<dyn JweDecrypter>::try_from(
+            serde_yaml::from_str::<EncryptionKeyConfig>(EC_PRIVKEY).unwrap(),
+        )
+        .unwrap();
+        let signer = Box::<dyn JwsSigner>::try_from(
+            serde_yaml::from_str::<SignKeyConfig>(EC_PRIVKEY).unwrap(),
+        )
+        .unwrap();
+        let verifier = Box::<dyn JwsVerifier>::try_from(
+            serde_yaml::from_str::<SignKeyConfig>(EC_PUBKEY).unwrap(),
+        )
+        .unwrap();
+
+        let in_result = AuthResult {
+            status: AuthStatus::Success,
+            attributes: Some(HashMap::from([("A".to_string(), "B".to_string())])),
+            session_url: None,
+        };
+        let jwe = sign_and_encrypt_auth_result(&in_result, signer.as_ref(), rsa_encrypter.as_ref())
+            .unwrap();
+        let reencrypted =
+            reencrypt_auth_result(&jwe, rsa_decrypter.as_ref(), ec_encrypter.as_ref()).unwrap();
+
+        // The original signature is kept
+        let out_result =
+            decrypt_and_verify_auth_result(&reencrypted, verifier.as_ref(), ec_decrypter.as_ref())
+                .unwrap();
+        assert_eq!(in_result, out_result);
+        assert!(decrypt_and_verify_auth_result(
+            &reencrypted,
+            verifier.as_ref(),
+            rsa_decrypter.as_ref()
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_log_hiding_rsa() {
         let dec_config: EncryptionKeyConfig = serde_yaml::from_str(RSA_PRIVKEY).unwrap();
@@ -1,5 +1,15 @@
 use serde::{Deserialize, Serialize};
 
+/// Optional ways of receiving authentication results supported at a
+/// continuation
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Result posted as `result` field of an automatically submitted html
+    /// form, instead of added to the url as `result` query parameter
+    FormPost,
+}
+
 /// Data sent along in a start_authentication request
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct StartAuthRequest {
@@ -11,6 +21,10 @@ pub struct StartAuthRequest {
     /// of authentication flow
     #[serde(skip_serializing_if = "Option::is_none")]
     pub attr_url: Option<String>,
+    /// Ways of receiving the result the continuation supports besides the
+    /// `result` query parameter
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<Capability>,
 }
 
 /// Result expected from a start_authentication request
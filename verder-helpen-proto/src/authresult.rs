@@ -29,6 +29,21 @@ pub struct AuthResult {
     pub session_url: Option<String>,
 }
 
+impl AuthResult {
+    /// Rename attributes to their canonical names, using a table of
+    /// plugin-specific names to canonical names. Attributes without an alias
+    /// are left untouched.
+    pub fn canonicalize_attributes(mut self, aliases: &HashMap<String, String>) -> Self {
+        self.attributes = self.attributes.map(|attributes| {
+            attributes
+                .into_iter()
+                .map(|(name, value)| (aliases.get(&name).cloned().unwrap_or(name), value))
+                .collect()
+        });
+        self
+    }
+}
+
 /// Session activity status update type
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, FromFormField)]
 pub enum SessionActivity {
@@ -1,11 +1,21 @@
 use serde::{Deserialize, Serialize};
 
 pub type Tag = String;
+
+fn default_as_true() -> bool {
+    true
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MethodProperties {
     pub tag: Tag,
     pub name: String,
     pub image_path: String,
+    #[serde(default = "default_as_true")]
+    pub available: bool,
+    /// RFC 3339 timestamp of the moment a closed method opens again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_opening: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,4 +35,22 @@ pub struct StartRequestAuthOnly {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ClientUrlResponse {
     pub client_url: String,
+    /// ID under which core keeps track of the session, if enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Auth method the session was started with, when chosen by core
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_method: Option<Tag>,
+    /// Urls for continuing the session on another device, when requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handoff: Option<Handoff>,
+}
+
+/// Urls through which a session can be continued on another device, either
+/// directly or by scanning a QR code
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Handoff {
+    pub url: String,
+    pub qr_svg: String,
+    pub qr_png: String,
 }
@@ -0,0 +1,297 @@
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use josekit::{
+    jws::{alg::hmac::HmacJwsAlgorithm::Hs256, JwsSigner},
+    JoseError,
+};
+use rocket::{
+    data::{self, Data, FromData, Limits},
+    http::Status,
+    mtls::Certificate,
+    outcome::Outcome,
+    Request,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+
+pub const SIGNATURE_HEADER: &str = "X-Verder-Helpen-Signature";
+
+// Maximum difference in seconds between the timestamp of a signed request and
+// the time it is received
+const SIGNATURE_TOLERANCE: u64 = 300;
+
+/// Signature header value for a body sent at the given unix timestamp, an
+/// HMAC-SHA256 over `<timestamp>.<body>`. The secret must be at least 32
+/// bytes.
+pub fn body_signature(secret: &[u8], timestamp: u64, body: &str) -> Result<String, JoseError> {
+    let mac = Hs256
+        .signer_from_bytes(secret)?
+        .sign(format!("{timestamp}.{body}").as_bytes())?;
+    let hex: String = mac.iter().map(|b| format!("{b:02x}")).collect();
+    Ok(format!("t={timestamp},v1={hex}"))
+}
+
+// Compare in constant time, so secrets can not be guessed byte by byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn verify_signature(secret: &[u8], header: &str, body: &str, now: u64) -> bool {
+    let Some(timestamp) = header
+        .split(',')
+        .find_map(|part| part.strip_prefix("t="))
+        .and_then(|t| t.parse::<u64>().ok())
+    else {
+        return false;
+    };
+    if now.abs_diff(timestamp) > SIGNATURE_TOLERANCE {
+        return false;
+    }
+    body_signature(secret, timestamp, body)
+        .is_ok_and(|expected| constant_time_eq(expected.as_bytes(), header.as_bytes()))
+}
+
+/// How a plugin checks that calls to its start endpoint come from core.
+/// Should match the `plugin_auth` configured for the method in core.
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CoreAuth {
+    /// Token core sends in the `Authorization` header
+    Bearer { token: String },
+    /// Secret with which core signs the request body, at least 32 bytes
+    Hmac { secret: String },
+    /// Client certificate issued by the CA in rocket's `tls.mutual`
+    /// configuration, optionally restricted to a subject common name
+    Mtls { common_name: Option<String> },
+}
+
+impl Debug for CoreAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoreAuth::Bearer { .. } => f.debug_struct("Bearer").finish_non_exhaustive(),
+            CoreAuth::Hmac { .. } => f.debug_struct("Hmac").finish_non_exhaustive(),
+            CoreAuth::Mtls { common_name } => f
+                .debug_struct("Mtls")
+                .field("common_name", common_name)
+                .finish(),
+        }
+    }
+}
+
+impl CoreAuth {
+    async fn verify(&self, request: &Request<'_>, body: &str) -> bool {
+        match self {
+            CoreAuth::Bearer { token } => request
+                .headers()
+                .get_one("Authorization")
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|candidate| constant_time_eq(candidate.as_bytes(), token.as_bytes())),
+            CoreAuth::Hmac { secret } => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                request
+                    .headers()
+                    .get_one(SIGNATURE_HEADER)
+                    .is_some_and(|header| verify_signature(secret.as_bytes(), header, body, now))
+            }
+            CoreAuth::Mtls { common_name } => match request.guard::<Certificate<'_>>().await {
+                Outcome::Success(certificate) => common_name.as_deref().map_or(true, |name| {
+                    certificate.subject().common_name() == Some(name)
+                }),
+                _ => false,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FromCoreError {
+    Io(std::io::Error),
+    TooLarge,
+    Unauthenticated,
+    Json(serde_json::Error),
+}
+
+/// JSON body of a call from core. When the plugin manages a [`CoreAuth`],
+/// calls that core did not authenticate are rejected with 401.
+#[derive(Debug)]
+pub struct FromCore<T>(pub T);
+
+impl<T> FromCore<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for FromCore<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for FromCore<T> {
+    type Error = FromCoreError;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let limit = request.limits().get("json").unwrap_or(Limits::JSON);
+        let body = match data.open(limit).into_string().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => return Outcome::Error((Status::PayloadTooLarge, FromCoreError::TooLarge)),
+            Err(e) => return Outcome::Error((Status::BadRequest, FromCoreError::Io(e))),
+        };
+
+        if let Some(auth) = request.rocket().state::<CoreAuth>() {
+            if !auth.verify(request, &body).await {
+                log::warn!(
+                    "Rejected call to {} not authenticated by core",
+                    request.uri()
+                );
+                return Outcome::Error((Status::Unauthorized, FromCoreError::Unauthenticated));
+            }
+        }
+
+        match serde_json::from_str(&body) {
+            Ok(value) => Outcome::Success(FromCore(value)),
+            Err(e) => Outcome::Error((Status::UnprocessableEntity, FromCoreError::Json(e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use rocket::{http::Status, local::blocking::Client, post, routes};
+
+    use super::{body_signature, verify_signature, CoreAuth, FromCore, SIGNATURE_HEADER};
+    use crate::StartCommRequest;
+
+    #[post("/start_communication", data = "<request>")]
+    fn start(request: FromCore<StartCommRequest>) -> String {
+        request.purpose.clone()
+    }
+
+    fn client(auth: Option<CoreAuth>) -> Client {
+        let base = rocket::build().mount("/", routes![start]);
+        let base = match auth {
+            Some(auth) => base.manage(auth),
+            None => base,
+        };
+        Client::tracked(base).unwrap()
+    }
+
+    const BODY: &str = r#"{"purpose":"test"}"#;
+    const SECRET: &str = "some_secret_12345678901234567890123";
+
+    #[test]
+    fn test_without_core_auth() {
+        let client = client(None);
+        let response = client.post("/start_communication").body(BODY).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().unwrap(), "test");
+    }
+
+    #[test]
+    fn test_bearer() {
+        let client = client(Some(CoreAuth::Bearer {
+            token: "sometoken".to_string(),
+        }));
+
+        let response = client.post("/start_communication").body(BODY).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .post("/start_communication")
+            .header(rocket::http::Header::new(
+                "Authorization",
+                "Bearer othertoken",
+            ))
+            .body(BODY)
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .post("/start_communication")
+            .header(rocket::http::Header::new(
+                "Authorization",
+                "Bearer sometoken",
+            ))
+            .body(BODY)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_hmac() {
+        let client = client(Some(CoreAuth::Hmac {
+            secret: SECRET.to_string(),
+        }));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let signature = body_signature(SECRET.as_bytes(), now, BODY).unwrap();
+
+        let response = client
+            .post("/start_communication")
+            .header(rocket::http::Header::new(
+                SIGNATURE_HEADER,
+                signature.clone(),
+            ))
+            .body(BODY)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        // Signature over a different body
+        let response = client
+            .post("/start_communication")
+            .header(rocket::http::Header::new(SIGNATURE_HEADER, signature))
+            .body(r#"{"purpose":"other"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn test_signature_age() {
+        let signature = body_signature(SECRET.as_bytes(), 1700000000, BODY).unwrap();
+        assert!(verify_signature(
+            SECRET.as_bytes(),
+            &signature,
+            BODY,
+            1700000100
+        ));
+        assert!(verify_signature(
+            SECRET.as_bytes(),
+            &signature,
+            BODY,
+            1699999900
+        ));
+        assert!(!verify_signature(
+            SECRET.as_bytes(),
+            &signature,
+            BODY,
+            1700000301
+        ));
+        assert!(!verify_signature(
+            b"other_secret_1234567890123456789012",
+            &signature,
+            BODY,
+            1700000000
+        ));
+    }
+
+    #[test]
+    fn test_mtls_without_certificate() {
+        let client = client(Some(CoreAuth::Mtls { common_name: None }));
+        let response = client.post("/start_communication").body(BODY).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+}
@@ -0,0 +1,144 @@
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::{HeaderMap, Status},
+    outcome::Outcome,
+    request::{self, FromRequest},
+    Request, Response,
+};
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Identification of a request, passed along on calls between core and
+/// plugins so their logs can be linked. Follows the W3C trace context for
+/// `traceparent`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Correlation {
+    request_id: String,
+    trace_id: String,
+    span_id: String,
+    flags: String,
+}
+
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+// Only identifiers that are safe to put in logs and headers are taken over
+fn valid_request_id(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 128
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+}
+
+// Trace ID and flags of a traceparent header
+fn parse_traceparent(value: &str) -> Option<(&str, &str)> {
+    let parts: Vec<&str> = value.trim().split('-').collect();
+    match parts[..] {
+        [version, trace_id, span_id, flags]
+            if is_hex(version, 2)
+                && version != "ff"
+                && is_hex(trace_id, 32)
+                && trace_id.chars().any(|c| c != '0')
+                && is_hex(span_id, 16)
+                && is_hex(flags, 2) =>
+        {
+            Some((trace_id, flags))
+        }
+        _ => None,
+    }
+}
+
+impl Correlation {
+    /// Start a new trace
+    pub fn generate() -> Self {
+        let trace_id = format!("{:032x}", rand::random::<u128>());
+        Correlation {
+            request_id: trace_id.clone(),
+            trace_id,
+            span_id: format!("{:016x}", rand::random::<u64>()),
+            flags: "00".to_string(),
+        }
+    }
+
+    /// Continue the trace of an incoming request, starting a new one if the
+    /// request does not carry valid identification. Without `X-Request-Id`,
+    /// the trace ID is used as request ID.
+    pub fn from_headers(headers: &HeaderMap<'_>) -> Self {
+        let mut correlation = Correlation::generate();
+        if let Some((trace_id, flags)) = headers
+            .get_one(TRACEPARENT_HEADER)
+            .and_then(parse_traceparent)
+        {
+            correlation.trace_id = trace_id.to_string();
+            correlation.request_id = trace_id.to_string();
+            correlation.flags = flags.to_string();
+        }
+        if let Some(request_id) = headers
+            .get_one(REQUEST_ID_HEADER)
+            .filter(|id| valid_request_id(id))
+        {
+            correlation.request_id = request_id.to_string();
+        }
+        correlation
+    }
+
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Value for the traceparent header of outgoing requests
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{}", self.trace_id, self.span_id, self.flags)
+    }
+
+    /// Headers to send along on outgoing requests
+    pub fn headers(&self) -> [(&'static str, String); 2] {
+        [
+            (REQUEST_ID_HEADER, self.request_id.clone()),
+            (TRACEPARENT_HEADER, self.traceparent()),
+        ]
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Correlation {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        Outcome::Success(request.local_cache(|| Correlation::from_headers(request.headers())))
+    }
+}
+
+/// Fairing adding the request ID to every response, and logging it for
+/// failed requests
+pub struct CorrelationFairing;
+
+#[rocket::async_trait]
+impl Fairing for CorrelationFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Correlation IDs",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let correlation = request.local_cache(|| Correlation::from_headers(request.headers()));
+        if response.status().code >= Status::BadRequest.code {
+            log::warn!(
+                "{} {} failed with {} (request id {})",
+                request.method(),
+                request.uri(),
+                response.status(),
+                correlation.request_id()
+            );
+        }
+        response.set_raw_header(REQUEST_ID_HEADER, correlation.request_id.clone());
+    }
+}
@@ -2,8 +2,12 @@ mod authplugin;
 mod authresult;
 mod common;
 mod commplugin;
+mod coreauth;
+mod correlation;
 
-pub use authplugin::{StartAuthRequest, StartAuthResponse};
+pub use authplugin::{Capability, StartAuthRequest, StartAuthResponse};
 pub use authresult::{AuthResult, AuthStatus, SessionActivity};
-pub use common::{ClientUrlResponse, SessionOptions, StartRequestAuthOnly};
+pub use common::{ClientUrlResponse, Handoff, SessionOptions, StartRequestAuthOnly};
 pub use commplugin::{StartCommRequest, StartCommResponse};
+pub use coreauth::{body_signature, CoreAuth, FromCore, FromCoreError, SIGNATURE_HEADER};
+pub use correlation::{Correlation, CorrelationFairing, REQUEST_ID_HEADER, TRACEPARENT_HEADER};